@@ -10,36 +10,77 @@ pub use plugin::execute_plugin;
 pub use syslog::send_syslog;
 pub use webhook::send_webhook;
 
-use crate::config::AlertsConfig;
+use crate::config::{AlertsConfig, Attribute};
+use crate::integrity::Change;
 use liquid::{ParserBuilder, object};
 use serde_json::json;
-use std::path::Path;
 use tracing::error;
 
 /// Dispatch a file-change alert to all enabled channels.
 /// If `cfg.payload_template` is `Some(tmpl)`, we try to render that Liquid template.
-/// Otherwise, we default to the fixed JSON: { "path": “…”, "old": “…", "new": "…" }.
-pub fn dispatch(cfg: &AlertsConfig, path: &Path, old: String, new: String) {
-    let path_str = path.display().to_string();
-
+/// Otherwise, we default to the fixed JSON:
+/// { "job": "…", "kind": "…", "path": "…", "from": …, "old": …, "new": …,
+///   "attributes": […], "diff": …, "findings": […] }
+/// where `old`/`new` are checksums (the first recorded digest), `attributes` lists
+/// each compared attribute that changed as
+/// { "attribute": "…", "old": "…", "new": "…" }, `diff` is the unified diff of a
+/// text file (or null) and `findings` lists what the change means for a well-known
+/// file as { "severity": "…", "message": "…" }.
+pub fn dispatch(cfg: &AlertsConfig, job_name: &str, change: &Change) {
     // 1) Build the payload string: either via Liquid or fallback to serde_json!
     let default_payload = json!({
-        "path": path_str,
-        "old": old,
-        "new": new,
+        "job": job_name,
+        "kind": change.kind,
+        "path": change.path,
+        "from": change.from,
+        "old": change.old_hash(),
+        "new": change.new_hash(),
+        "attributes": change.attributes,
+        "diff": change.diff,
+        "findings": change.findings,
     })
     .to_string();
 
+    // e.g. "mode: 0644 -> 4755, uid: 0 -> 1000"
+    let changed_attributes = change
+        .attributes
+        .iter()
+        .filter(|a| a.attribute != Attribute::Content)
+        .map(|a| format!("{}: {} -> {}", a.attribute.as_str(), a.old, a.new))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    // e.g. "critical: user toor added with uid 0; info: user backup added with uid 34"
+    let findings = change
+        .findings
+        .iter()
+        .map(|f| f.to_string())
+        .collect::<Vec<_>>()
+        .join("; ");
+    // Findings are sorted most severe first
+    let severity = change
+        .findings
+        .first()
+        .map(|f| f.severity.as_str())
+        .unwrap_or_default();
+
     let payload = if let Some(template_str) = &cfg.payload_template {
         // Try to compile & render the Liquid template
         match ParserBuilder::with_stdlib().build() {
             Ok(parser) => match parser.parse(template_str) {
                 Ok(template) => {
-                    // Create the Liquid "globals" object
+                    // Create the Liquid "globals" object. Absent values render as "".
                     let globals = object!({
-                        "path": path_str.clone(),
-                        "old": old.clone(),
-                        "new": new.clone(),
+                        "job_name": job_name.to_string(),
+                        "kind": change.kind.as_str(),
+                        "path": change.path.clone(),
+                        "from": change.from.clone().unwrap_or_default(),
+                        "old": change.old_hash().unwrap_or_default().to_string(),
+                        "new": change.new_hash().unwrap_or_default().to_string(),
+                        "attributes": changed_attributes.clone(),
+                        "diff": change.diff.clone().unwrap_or_default(),
+                        "findings": findings.clone(),
+                        "severity": severity.to_string(),
                     });
                     match template.render(&globals) {
                         Ok(output) => output, // successfully rendered
@@ -72,23 +113,24 @@ pub fn dispatch(cfg: &AlertsConfig, path: &Path, old: String, new: String) {
     }
 
     // 3) Webhook
-    if let Some(url) = &cfg.webhook_url {
-        if let Err(e) = send_webhook(url, &payload) {
-            error!("webhook alert to {} failed: {}", url, e);
-        }
+    if let Some(url) = &cfg.webhook_url
+        && let Err(e) = send_webhook(url, &payload)
+    {
+        error!("webhook alert to {} failed: {}", url, e);
     }
 
     // 4) Local script
-    if let Some(script) = &cfg.script_path {
-        if let Err(e) = execute_script(script) {
-            error!("script alert `{}` failed: {}", script, e);
-        }
+    if let Some(script) = &cfg.script_path
+        && let Err(e) = execute_script(script)
+    {
+        error!("script alert `{}` failed: {}", script, e);
     }
 
     // 5) Plugin
-    if let Some(plugin_path) = &cfg.plugin_path {
-        if let Err(e) = execute_plugin(plugin_path, &payload) {
-            error!("plugin alert `{}` failed: {}", plugin_path, e);
-        }
+    if let Some(plugin_path) = &cfg.plugin_path
+        && let Err(e) = execute_plugin(plugin_path, &payload)
+    {
+        error!("plugin alert `{}` failed: {}", plugin_path, e);
     }
 }
+
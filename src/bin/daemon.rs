@@ -25,5 +25,6 @@ fn main() -> Result<()> {
 fn load_config(path: &str) -> Result<Config> {
     let s = fs::read_to_string(path).context("Reading daemon config")?;
     let c: Config = serde_yaml::from_str(&s).context("Parsing daemon config")?;
+    c.validate()?;
     Ok(c)
 }
\ No newline at end of file
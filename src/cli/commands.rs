@@ -1,5 +1,69 @@
 // CLI commands logic
-use clap::Subcommand;
+use crate::config::{Attribute, SigningMethod};
+use clap::{Subcommand, ValueEnum};
+
+/// Output format of `check`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Human,
+    Json,
+}
+
+/// Output format of `diff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DiffFormat {
+    Text,
+    Json,
+    Markdown,
+}
+
+/// What `history` does with a job's baseline snapshots.
+#[derive(Subcommand)]
+pub enum HistoryAction {
+    /// List the snapshots, oldest first
+    List {
+        #[arg(short, long)]
+        job: String,
+    },
+
+    /// Show the changes between two snapshots
+    Diff {
+        #[arg(short, long)]
+        job: String,
+
+        /// Snapshot id (or unique prefix, or `latest`)
+        from: String,
+
+        /// Snapshot to compare with (default: the active baseline)
+        to: Option<String>,
+    },
+
+    /// Make an earlier snapshot the active baseline again
+    Rollback {
+        #[arg(short, long)]
+        job: String,
+
+        /// Snapshot id (or unique prefix)
+        id: String,
+    },
+}
+
+/// What `store` does with the content store.
+#[derive(Subcommand)]
+pub enum StoreAction {
+    /// Print (or write to a file) a stored content, e.g. the `old` checksum of an alert
+    Cat {
+        /// Hex digest, optionally prefixed with its algorithm (`sha256:…`)
+        hash: String,
+
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        out: Option<String>,
+    },
+
+    /// Apply the retention limits (`max_size`, `max_age_days`) now
+    Prune,
+}
 
 #[derive(Subcommand)]
 pub enum Commands {
@@ -22,4 +86,95 @@ pub enum Commands {
         #[arg(short, long)]
         daemon: bool,
     },
+
+    /// Compare the disk against the stored baselines once and exit
+    /// (0 = clean, 1 = changes found, 2 = error)
+    Check {
+        /// Only check this job
+        #[arg(short, long)]
+        job: Option<String>,
+
+        #[arg(short, long, value_enum, default_value = "human")]
+        format: ReportFormat,
+    },
+
+    /// Compare two baseline files, e.g. a golden image and a host
+    /// (0 = identical, 1 = different, 2 = error)
+    Diff {
+        from: String,
+        to: String,
+
+        #[arg(short, long, value_enum, default_value = "text")]
+        format: DiffFormat,
+
+        /// Only compare paths at or below this one (repeatable)
+        #[arg(short, long = "path")]
+        paths: Vec<String>,
+
+        /// Leave out paths matching this gitignore-style pattern (repeatable)
+        #[arg(short, long)]
+        exclude: Vec<String>,
+
+        /// Attributes to compare, comma-separated (default: content,type,mode,uid,gid,size)
+        #[arg(short, long, value_delimiter = ',')]
+        attributes: Vec<Attribute>,
+
+        /// Check both files' signatures with the keys of the config's `signing` section
+        #[arg(long)]
+        verify: bool,
+    },
+
+    /// Run as a Nagios/Icinga plugin: status line with perfdata, exit code
+    /// 0 = OK, 1 = WARNING, 2 = CRITICAL, 3 = UNKNOWN
+    Nagios {
+        /// Only check this job
+        #[arg(short, long)]
+        job: Option<String>,
+    },
+
+    /// Approve pending changes of a job into its baseline, leaving every other
+    /// entry untouched. Acceptances are logged to `audit_<job>.jsonl`
+    Accept {
+        /// The job whose baseline is updated
+        #[arg(short, long)]
+        job: String,
+
+        /// Review each pending change and answer y/n/q
+        #[arg(short, long, conflicts_with = "paths")]
+        interactive: bool,
+
+        /// Files or directories whose changes are accepted
+        #[arg(required_unless_present = "interactive")]
+        paths: Vec<String>,
+    },
+
+    /// List, compare or roll back the baseline snapshots kept under `history/<job>/`
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+
+    /// Recover file contents kept by jobs with `store_contents`
+    Store {
+        #[command(subcommand)]
+        action: StoreAction,
+    },
+
+    /// Create a baseline signing key (Ed25519 also writes `<out>.pub`)
+    Keygen {
+        /// `ed25519` or `hmac-sha256`
+        #[arg(short, long, default_value = "ed25519")]
+        method: SigningMethod,
+
+        /// Where to write the private key
+        #[arg(short, long)]
+        out: String,
+    },
+
+    /// Sign existing baselines with the key configured under `signing`
+    Resign {
+        /// Only re-sign this job's baseline
+        #[arg(short, long)]
+        job: Option<String>,
+    },
 }
@@ -52,11 +52,12 @@ pub fn init_command(path: &str) -> Result<()> {
 mod tests {
     use super::Cli;
     use clap::Parser;
+    use crate::config::Attribute;
 
     #[test]
     fn default_config_and_no_selfcheck() {
         // note: subcommands are lowercase
-        let args = Cli::parse_from(&["watchdogfs", "baseline"]);
+        let args = Cli::parse_from(["watchdogfs", "baseline"]);
         assert_eq!(args.config_path(), "config.yaml");
         assert!(args.self_integrity_path().is_none());
         match args.command {
@@ -67,7 +68,7 @@ mod tests {
 
     #[test]
     fn custom_config_and_selfcheck() {
-        let args = Cli::parse_from(&[
+        let args = Cli::parse_from([
             "watchdogfs",
             "-c",
             "custom.yaml",
@@ -90,7 +91,7 @@ mod tests {
         // Now we must provide at least the boolean (even if false), or adjust the signature.
         // By default, `with_baseline` is false, so tests can stay the same:
 
-        let args = Cli::parse_from(&["watchdogfs", "init", "-c", "foo.yaml"]);
+        let args = Cli::parse_from(["watchdogfs", "init", "-c", "foo.yaml"]);
         match args.command {
             super::Commands::Init {
                 config,
@@ -105,7 +106,7 @@ mod tests {
 
     #[test]
     fn init_with_baseline_parses() {
-        let args = Cli::parse_from(&["watchdogfs", "init", "-c", "foo.yaml", "--with-baseline"]);
+        let args = Cli::parse_from(["watchdogfs", "init", "-c", "foo.yaml", "--with-baseline"]);
         match args.command {
             super::Commands::Init {
                 config,
@@ -117,4 +118,60 @@ mod tests {
             _ => panic!("expected Init command"),
         }
     }
+
+    #[test]
+    fn keygen_and_resign_parse() {
+        let args = Cli::parse_from(["watchdogfs", "keygen", "-m", "hmac-sha256", "-o", "k"]);
+        match args.command {
+            super::Commands::Keygen { method, out } => {
+                assert_eq!(method, crate::config::SigningMethod::HmacSha256);
+                assert_eq!(out, "k");
+            }
+            _ => panic!("expected Keygen command"),
+        }
+        assert!(Cli::try_parse_from(["watchdogfs", "keygen", "-m", "rsa", "-o", "k"]).is_err());
+
+        let args = Cli::parse_from(["watchdogfs", "resign", "--job", "web"]);
+        match args.command {
+            super::Commands::Resign { job } => assert_eq!(job.as_deref(), Some("web")),
+            _ => panic!("expected Resign command"),
+        }
+    }
+
+    #[test]
+    fn check_parses_job_and_format() {
+        let args = Cli::parse_from(["watchdogfs", "check", "--job", "web", "--format", "json"]);
+        match args.command {
+            super::Commands::Check { job, format } => {
+                assert_eq!(job.as_deref(), Some("web"));
+                assert_eq!(format, super::ReportFormat::Json);
+            }
+            _ => panic!("expected Check command"),
+        }
+        let args = Cli::parse_from(["watchdogfs", "check"]);
+        assert!(matches!(
+            args.command,
+            super::Commands::Check { job: None, format: super::ReportFormat::Human }
+        ));
+    }
+
+    #[test]
+    fn diff_parses_filters_and_attributes() {
+        let args = Cli::parse_from([
+            "watchdogfs", "diff", "golden.json", "host.json", "-f", "markdown", "--path", "/etc",
+            "--exclude", "*.log", "--attributes", "content,mode",
+        ]);
+        match args.command {
+            super::Commands::Diff { from, paths, exclude, attributes, format, verify, .. } => {
+                assert_eq!(from, "golden.json");
+                assert_eq!(paths, ["/etc"]);
+                assert_eq!(exclude, ["*.log"]);
+                assert_eq!(attributes, [Attribute::Content, Attribute::Mode]);
+                assert_eq!(format, super::DiffFormat::Markdown);
+                assert!(!verify);
+            }
+            _ => panic!("expected Diff command"),
+        }
+        assert!(Cli::try_parse_from(["watchdogfs", "diff", "a", "b", "-a", "colour"]).is_err());
+    }
 }
@@ -1,6 +1,13 @@
 // YAML configuration parsing (serde_yaml)
+mod schedule;
 mod settings;
-pub use settings::{Config, JobConfig, AlertsConfig, WatcherConfig};
+pub use schedule::Schedule;
+pub use settings::{
+    AlertsConfig, Attribute, BaselineUpdate, Config, ContentStoreConfig, DebounceMode,
+    HashAlgorithm, JobConfig, LargeFilePolicy, NagiosThresholds, Remediation, RestoreLimit,
+    SigningConfig, SigningMethod, TextDiffConfig, WatcherConfig, default_attributes,
+    default_hash_algorithms,
+};
 
 use std::{fs, path::Path};
 use anyhow::{Context, Result};
@@ -21,12 +28,14 @@ pub fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
         .with_context(|| format!("reading config file {:?}", path.as_ref()))?;
     let cfg: Config = serde_yaml::from_str(&s)
         .context("parsing YAML config")?;
+    cfg.validate()?;
     Ok(cfg)
 }
 
 #[cfg(test)]
 mod tests {
     use super::{write_default, load};
+    use std::fs;
     use tempfile::NamedTempFile;
 
     #[test]
@@ -39,4 +48,84 @@ mod tests {
         assert!(cfg.jobs.is_empty());
         // The YAML should parse back to the same structure
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn empty_hash_algorithms_are_rejected() {
+        let tmp = NamedTempFile::new().unwrap();
+        let yaml = "jobs:
+  web:
+    watch_paths: [/var/www]
+    ignore_patterns: []
+    hash_algorithms: []
+    alerts:
+      use_syslog: false
+    watcher:
+      mode: polling
+";
+        fs::write(tmp.path(), yaml).unwrap();
+        let err = load(tmp.path()).unwrap_err();
+        assert!(format!("{:#}", err).contains("hash_algorithms"));
+
+        fs::write(tmp.path(), yaml.replace("[]\n    alerts", "[blake3]\n    alerts")).unwrap();
+        assert!(load(tmp.path()).is_ok());
+    }
+
+    #[test]
+    fn nagios_thresholds_are_validated() {
+        let tmp = NamedTempFile::new().unwrap();
+        let with = |nagios: &str| {
+            let yaml = format!(
+                "jobs:
+  web:
+    watch_paths: [/var/www]
+    ignore_patterns: []
+    nagios: {}
+    alerts:
+      use_syslog: false
+    watcher:
+      mode: polling
+",
+                nagios
+            );
+            fs::write(tmp.path(), yaml).unwrap();
+            load(tmp.path())
+        };
+        assert!(with("{warning: 1, critical: 10}").is_ok());
+        let err = with("{warning: 10, critical: 1}").unwrap_err();
+        assert!(format!("{:#}", err).contains("above the critical"));
+        assert!(with("{warning: 0, critical: 5}").is_err());
+    }
+
+    #[test]
+    fn schedule_is_validated() {
+        let tmp = NamedTempFile::new().unwrap();
+        let with = |watcher: &str| {
+            let yaml = format!(
+                "jobs:
+  bin:
+    watch_paths: [/usr/bin]
+    ignore_patterns: []
+    alerts:
+      use_syslog: false
+    watcher:
+      mode: scheduled
+{}",
+                watcher
+            );
+            fs::write(tmp.path(), yaml).unwrap();
+            load(tmp.path()).map_err(|e| format!("{:#}", e))
+        };
+        assert!(with("      schedule: '0 3 * * *'\n      jitter_secs: 600\n").is_ok());
+        assert!(with("").unwrap_err().contains("needs a `schedule`"));
+        assert!(
+            with("      schedule: '0 25 * * *'\n")
+                .unwrap_err()
+                .contains("invalid schedule")
+        );
+        assert!(
+            with("      schedule: '*/5 * * * *'\n      jitter_secs: 300\n")
+                .unwrap_err()
+                .contains("jitter_secs (300)")
+        );
+    }
+}
@@ -0,0 +1,172 @@
+// Run times of `mode: scheduled` jobs
+use crate::config::WatcherConfig;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use croner::Cron;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// More missed runs than this are not counted.
+const MAX_MISSED: usize = 1000;
+/// How many upcoming runs are looked at to find the shortest gap between two.
+const GAP_SAMPLE: usize = 100;
+
+/// A cron expression (`minute hour day month weekday`, in local time) plus up to
+/// `jitter` of random delay per run, so many hosts do not scan at the same second.
+#[derive(Debug)]
+pub struct Schedule {
+    cron: Cron,
+    jitter: Duration,
+}
+
+impl Schedule {
+    /// Fails if `expression` does not parse or never matches, or if `jitter` is not
+    /// shorter than the time between two runs, which would let runs overtake each other.
+    pub fn new(expression: &str, jitter: Duration) -> Result<Self> {
+        let cron = Cron::from_str(expression)
+            .with_context(|| format!("invalid schedule {:?}", expression))?;
+        let schedule = Schedule { cron, jitter };
+        let mut time = schedule.next_after(&Local::now())?;
+        if jitter.is_zero() {
+            return Ok(schedule);
+        }
+        for _ in 0..GAP_SAMPLE {
+            let Ok(next) = schedule.next_after(&time) else {
+                break;
+            };
+            let gap = (next - time).to_std().unwrap_or_default();
+            if jitter >= gap {
+                anyhow::bail!(
+                    "jitter_secs ({}) must be shorter than the {}s between runs of {:?}",
+                    jitter.as_secs(),
+                    gap.as_secs(),
+                    expression
+                );
+            }
+            time = next;
+        }
+        Ok(schedule)
+    }
+
+    /// The schedule of a `scheduled` job: its `schedule`, delayed by up to `jitter_secs`.
+    pub fn for_watcher(cfg: &WatcherConfig) -> Result<Self> {
+        let expression = cfg
+            .schedule
+            .as_deref()
+            .context("mode `scheduled` needs a `schedule`")?;
+        Schedule::new(
+            expression,
+            Duration::from_secs(cfg.jitter_secs.unwrap_or(0)),
+        )
+    }
+
+    /// The first run time after `after`, before jitter.
+    pub fn next_after(&self, after: &DateTime<Local>) -> Result<DateTime<Local>> {
+        self.cron
+            .find_next_occurrence(after, false)
+            .with_context(|| format!("no run of {:?} after {}", self.cron.pattern, after))
+    }
+
+    /// The next run after `after`, delayed by a random part of the jitter.
+    pub fn next_run(&self, after: &DateTime<Local>) -> Result<DateTime<Local>> {
+        let delay = fastrand::u64(0..=self.jitter.as_secs());
+        Ok(self.next_after(after)? + chrono::Duration::seconds(delay as i64))
+    }
+
+    /// How many run times fall after `since` and no later than `until`.
+    pub fn runs_between(&self, since: &DateTime<Local>, until: &DateTime<Local>) -> usize {
+        let mut count = 0;
+        let mut time = *since;
+        while count < MAX_MISSED {
+            match self.next_after(&time) {
+                Ok(next) if next <= *until => {
+                    count += 1;
+                    time = next;
+                }
+                _ => break,
+            }
+        }
+        count
+    }
+
+    /// When to run after a pass that came due at `due` finished at `now`, and how many
+    /// run times passed meanwhile: with `catch_up`, right away if any did; otherwise
+    /// the next run after `now`, skipping them.
+    pub fn after_pass(
+        &self,
+        due: &DateTime<Local>,
+        now: &DateTime<Local>,
+        catch_up: bool,
+    ) -> Result<(DateTime<Local>, usize)> {
+        let missed = self.runs_between(due, now);
+        if missed > 0 && catch_up {
+            return Ok((*now, missed));
+        }
+        Ok((self.next_run(now)?, missed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Schedule;
+    use chrono::{Local, TimeZone};
+    use std::time::Duration;
+
+    #[test]
+    fn runs_follow_the_cron_expression() {
+        let schedule = Schedule::new("0 3 * * *", Duration::ZERO).unwrap();
+        let at = |d, h, m| Local.with_ymd_and_hms(2026, 10, d, h, m, 0).unwrap();
+        assert_eq!(schedule.next_after(&at(17, 10, 0)).unwrap(), at(18, 3, 0));
+        assert_eq!(schedule.next_after(&at(18, 2, 59)).unwrap(), at(18, 3, 0));
+        assert_eq!(schedule.next_run(&at(18, 3, 0)).unwrap(), at(19, 3, 0));
+
+        assert_eq!(schedule.runs_between(&at(17, 3, 0), &at(17, 23, 0)), 0);
+        assert_eq!(schedule.runs_between(&at(17, 3, 0), &at(20, 3, 0)), 3);
+    }
+
+    #[test]
+    fn jitter_delays_within_bounds() {
+        let schedule = Schedule::new("*/15 * * * *", Duration::from_secs(60)).unwrap();
+        let now = Local.with_ymd_and_hms(2026, 10, 17, 10, 5, 0).unwrap();
+        let due = schedule.next_after(&now).unwrap();
+        for _ in 0..20 {
+            let run = schedule.next_run(&now).unwrap();
+            assert!(run >= due && run <= due + chrono::Duration::seconds(60));
+        }
+    }
+
+    #[test]
+    fn overlapping_runs_are_skipped_or_caught_up() {
+        let schedule = Schedule::new("*/15 * * * *", Duration::ZERO).unwrap();
+        let at = |h, m| Local.with_ymd_and_hms(2026, 10, 17, h, m, 0).unwrap();
+
+        // A pass from 10:00 to 10:40 overran the runs at 10:15 and 10:30
+        assert_eq!(
+            schedule.after_pass(&at(10, 0), &at(10, 40), false).unwrap(),
+            (at(10, 45), 2)
+        );
+        assert_eq!(
+            schedule.after_pass(&at(10, 0), &at(10, 40), true).unwrap(),
+            (at(10, 40), 2)
+        );
+        // A pass that ends in time waits for the next run either way
+        assert_eq!(
+            schedule.after_pass(&at(10, 0), &at(10, 10), true).unwrap(),
+            (at(10, 15), 0)
+        );
+    }
+
+    #[test]
+    fn invalid_expression_is_rejected() {
+        let err = Schedule::new("0 25 * * *", Duration::ZERO).unwrap_err();
+        assert!(err.to_string().contains("invalid schedule"));
+        assert!(Schedule::new("0 0 31 2 *", Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn jitter_must_be_shorter_than_the_interval() {
+        let err = Schedule::new("*/15 * * * *", Duration::from_secs(900)).unwrap_err();
+        assert!(err.to_string().contains("jitter_secs (900)"), "{}", err);
+        assert!(Schedule::new("0 3 * * *", Duration::from_secs(3600)).is_ok());
+    }
+}
@@ -1,16 +1,75 @@
 // Config structures
 
+use super::schedule::Schedule;
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct JobConfig {
     /// Which paths this job should watch
     pub watch_paths: Vec<String>,
 
-    /// Which glob patterns to ignore (not yet used but reserved)
+    /// gitignore-style patterns (`*.swp`, `**/cache/`, `!keep.tmp`) for paths that
+    /// never produce baseline entries or alerts
     pub ignore_patterns: Vec<String>,
 
+    /// How many directory levels below each watch path to descend
+    /// (`1` = only direct children). `None` means unlimited.
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+
+    /// If true, never cross into another filesystem while walking directories
+    #[serde(default)]
+    pub one_file_system: bool,
+
+    /// Which recorded attributes are compared against the baseline
+    #[serde(default = "default_attributes")]
+    pub attributes: Vec<Attribute>,
+
+    /// Digests recorded for each file (several may be listed)
+    #[serde(default = "default_hash_algorithms")]
+    pub hash_algorithms: Vec<HashAlgorithm>,
+
+    /// Files larger than this many bytes are handled per `large_files`.
+    /// `None` hashes every file fully.
+    #[serde(default)]
+    pub max_file_size: Option<u64>,
+
+    /// How files above `max_file_size` are hashed
+    #[serde(default)]
+    pub large_files: LargeFilePolicy,
+
+    /// What to do with `baseline_<job>.json` after a change has been alerted
+    #[serde(default)]
+    pub baseline_update: BaselineUpdate,
+
+    /// Keep compressed copies of the recorded contents in the `content_store`
+    #[serde(default)]
+    pub store_contents: bool,
+
+    /// What to do about a modified or deleted file besides alerting
+    #[serde(default)]
+    pub remediation: Remediation,
+
+    /// Loop protection for `remediation: restore`
+    #[serde(default)]
+    pub restore_limit: RestoreLimit,
+
+    /// If set, alerts on modified text files carry a unified diff against the
+    /// stored previous content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_diff: Option<TextDiffConfig>,
+
+    /// Describe changes to accounts, sudoers, sshd_config, crontabs and
+    /// authorized_keys as findings with severities in alerts
+    #[serde(default)]
+    pub semantic_findings: bool,
+
+    /// How many changes make `watchdogfs nagios` report WARNING / CRITICAL
+    #[serde(default)]
+    pub nagios: NagiosThresholds,
+
     /// Per‐job alert settings
     pub alerts: AlertsConfig,
 
@@ -18,6 +77,64 @@ pub struct JobConfig {
     pub watcher: WatcherConfig,
 }
 
+impl Default for JobConfig {
+    fn default() -> Self {
+        JobConfig {
+            watch_paths: Vec::new(),
+            ignore_patterns: Vec::new(),
+            max_depth: None,
+            one_file_system: false,
+            attributes: default_attributes(),
+            hash_algorithms: default_hash_algorithms(),
+            max_file_size: None,
+            large_files: LargeFilePolicy::default(),
+            baseline_update: BaselineUpdate::default(),
+            store_contents: false,
+            remediation: Remediation::default(),
+            restore_limit: RestoreLimit::default(),
+            text_diff: None,
+            semantic_findings: false,
+            nagios: NagiosThresholds::default(),
+            alerts: AlertsConfig::default(),
+            watcher: WatcherConfig::default(),
+        }
+    }
+}
+
+impl JobConfig {
+    /// Whether this job keeps file contents in the content store, which
+    /// `remediation: restore`, `text_diff` and `semantic_findings` need.
+    pub fn keeps_contents(&self) -> bool {
+        self.store_contents
+            || self.remediation == Remediation::Restore
+            || self.text_diff.is_some()
+            || self.semantic_findings
+    }
+
+    /// Reject settings that parse but cannot work.
+    pub fn validate(&self) -> Result<()> {
+        if self.hash_algorithms.is_empty() {
+            // No digests would mean no content comparison and no rename detection
+            anyhow::bail!("hash_algorithms must list at least one algorithm");
+        }
+        let nagios = self.nagios;
+        if nagios.warning == 0 || nagios.critical == 0 {
+            anyhow::bail!("nagios thresholds must be at least 1 change");
+        }
+        if nagios.warning > nagios.critical {
+            anyhow::bail!(
+                "nagios warning threshold ({}) is above the critical one ({})",
+                nagios.warning,
+                nagios.critical
+            );
+        }
+        if self.watcher.mode == "scheduled" {
+            Schedule::for_watcher(&self.watcher)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct Config {
     /// A map from “job name” to its configuration
@@ -25,13 +142,375 @@ pub struct Config {
 
     /// Optional path to a file containing the expected SHA256 of this binary (self‐integrity)
     pub self_integrity_path: Option<String>,
+
+    /// If set, baselines are signed when written and verified whenever they are loaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing: Option<SigningConfig>,
+
+    /// How many baseline snapshots to keep per job under `history/<job>/` (default: all)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_keep: Option<usize>,
+
+    /// Where jobs with `store_contents` keep copies of file contents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_store: Option<ContentStoreConfig>,
+}
+
+impl Config {
+    /// Validate every job, naming the first one that is invalid.
+    pub fn validate(&self) -> Result<()> {
+        for (name, job) in &self.jobs {
+            job.validate()
+                .with_context(|| format!("invalid settings for job '{}'", name))?;
+        }
+        Ok(())
+    }
+}
+
+/// Location and retention of the content store, shared by all jobs so identical
+/// contents are kept once.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct ContentStoreConfig {
+    #[serde(default = "default_store_path")]
+    pub path: String,
+
+    /// Files larger than this many bytes are not stored
+    #[serde(default = "default_store_max_file_size")]
+    pub max_file_size: u64,
+
+    /// Total bytes above which contents no baseline refers to are removed, oldest first
+    #[serde(default)]
+    pub max_size: Option<u64>,
+
+    /// Contents no baseline refers to are removed once unused for this many days
+    #[serde(default)]
+    pub max_age_days: Option<u64>,
+}
+
+fn default_store_path() -> String {
+    "content_store".to_string()
+}
+
+fn default_store_max_file_size() -> u64 {
+    16 * 1024 * 1024
+}
+
+impl Default for ContentStoreConfig {
+    fn default() -> Self {
+        ContentStoreConfig {
+            path: default_store_path(),
+            max_file_size: default_store_max_file_size(),
+            max_size: None,
+            max_age_days: None,
+        }
+    }
+}
+
+/// Keys used to sign and verify baseline files.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct SigningConfig {
+    pub method: SigningMethod,
+
+    /// Ed25519 private key (hex) or HMAC secret. Needed to write baselines;
+    /// an Ed25519 host with only `public_key_path` can verify but not sign.
+    #[serde(default)]
+    pub key_path: Option<String>,
+
+    /// Ed25519 public key (hex) used for verification; derived from
+    /// `key_path` when omitted
+    #[serde(default)]
+    pub public_key_path: Option<String>,
+}
+
+/// How baselines are signed.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SigningMethod {
+    Ed25519,
+    HmacSha256,
+}
+
+impl SigningMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SigningMethod::Ed25519 => "ed25519",
+            SigningMethod::HmacSha256 => "hmac-sha256",
+        }
+    }
+}
+
+impl std::str::FromStr for SigningMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ed25519" => Ok(SigningMethod::Ed25519),
+            "hmac-sha256" => Ok(SigningMethod::HmacSha256),
+            other => Err(format!(
+                "unknown signing method `{}` (expected ed25519 or hmac-sha256)",
+                other
+            )),
+        }
+    }
+}
+
+/// A property of a file that can be compared against its baseline entry.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Attribute {
+    /// Content checksum
+    Content,
+    /// File type (regular file, symlink, …)
+    Type,
+    /// Permission bits, including setuid/setgid/sticky
+    Mode,
+    Uid,
+    Gid,
+    Size,
+    Inode,
+    /// Hard link count
+    Nlink,
+    Mtime,
+    Ctime,
+    /// All extended attributes (names and values)
+    Xattrs,
+    /// POSIX access and default ACLs
+    Acl,
+    /// SELinux label (`security.selinux`)
+    Selinux,
+    /// File capabilities (`security.capability`)
+    Capabilities,
+    /// Inode flags such as immutable and append-only (`lsattr`)
+    Flags,
+}
+
+impl Attribute {
+    /// The name used in `config.yaml` and alert payloads.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Attribute::Content => "content",
+            Attribute::Type => "type",
+            Attribute::Mode => "mode",
+            Attribute::Uid => "uid",
+            Attribute::Gid => "gid",
+            Attribute::Size => "size",
+            Attribute::Inode => "inode",
+            Attribute::Nlink => "nlink",
+            Attribute::Mtime => "mtime",
+            Attribute::Ctime => "ctime",
+            Attribute::Xattrs => "xattrs",
+            Attribute::Acl => "acl",
+            Attribute::Selinux => "selinux",
+            Attribute::Capabilities => "capabilities",
+            Attribute::Flags => "flags",
+        }
+    }
+}
+
+impl std::str::FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_yaml::from_str(s).map_err(|_| format!("unknown hash algorithm `{}`", s))
+    }
+}
+
+impl std::str::FromStr for Attribute {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_yaml::from_str(s).map_err(|_| format!("unknown attribute `{}`", s))
+    }
+}
+
+/// Compared unless a job lists its own `attributes`. Timestamps, inode and link
+/// count change on benign operations (touch, package upgrades) and are opt-in,
+/// as are xattrs, ACLs, SELinux labels, capabilities and inode flags, which
+/// cost extra syscalls per file.
+pub fn default_attributes() -> Vec<Attribute> {
+    vec![
+        Attribute::Content,
+        Attribute::Type,
+        Attribute::Mode,
+        Attribute::Uid,
+        Attribute::Gid,
+        Attribute::Size,
+    ]
+}
+
+/// A content digest algorithm.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// The name used in `config.yaml` and baseline files.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+/// Used unless a job lists its own `hash_algorithms`; matches what baselines
+/// recorded before the algorithm was configurable.
+pub fn default_hash_algorithms() -> Vec<HashAlgorithm> {
+    vec![HashAlgorithm::Sha256]
+}
+
+/// What to do with files larger than a job's `max_file_size`.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LargeFilePolicy {
+    /// Record metadata only; content is not hashed or compared
+    Skip,
+    /// Hash only the first and the last MiB
+    HeadTail,
+    /// Hash the whole file anyway (streamed, so memory stays bounded)
+    #[default]
+    Full,
+}
+
+/// Baseline persistence policy of a job.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BaselineUpdate {
+    /// Fold every alerted change into the baseline and persist it, so each change
+    /// is reported once (also across restarts).
+    #[default]
+    UpdateOnAlert,
+
+    /// Never modify the baseline from the watcher: a change keeps alerting until
+    /// it is accepted (e.g. by regenerating the baseline).
+    #[serde(alias = "never-update")]
+    AlertUntilAccepted,
+}
+
+/// What a job does about a modified or deleted file.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Remediation {
+    /// Only alert
+    #[default]
+    Alert,
+    /// Write back the recorded content, owner and mode from the content store
+    Restore,
+}
+
+/// At most `max_restores` restores of the same path within `window_secs`; beyond
+/// that the job only alerts, so two writers cannot fight forever.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct RestoreLimit {
+    pub max_restores: usize,
+    pub window_secs: u64,
+}
+
+impl Default for RestoreLimit {
+    fn default() -> Self {
+        RestoreLimit {
+            max_restores: 3,
+            window_secs: 600,
+        }
+    }
+}
+
+/// Unified diffs of modified text files in alert payloads.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct TextDiffConfig {
+    /// Files larger than this many bytes (before or after the change) get no diff
+    #[serde(default = "default_text_diff_max_file_size")]
+    pub max_file_size: u64,
+
+    /// Unchanged lines shown around each change
+    #[serde(default = "default_text_diff_context")]
+    pub context_lines: usize,
+
+    /// Regular expressions whose matches are masked before diffing; only the
+    /// group named `secret` is masked if the pattern has one
+    #[serde(default = "default_redact")]
+    pub redact: Vec<String>,
+}
+
+fn default_text_diff_max_file_size() -> u64 {
+    64 * 1024
+}
+
+fn default_text_diff_context() -> usize {
+    3
+}
+
+/// Password-like assignments (`password = …`, `api_key: …`), PEM private keys and
+/// the password hash of `/etc/shadow` lines (`name:hash:` and seven more fields).
+fn default_redact() -> Vec<String> {
+    vec![
+        r"(?im)^\s*[\w.-]*(?:password|passwd|passphrase|secret|token|api[_-]?key)[\w.-]*\s*[:=]\s*(?P<secret>\S.*?)\s*$".into(),
+        r"(?s)-----BEGIN [A-Z0-9 ]*PRIVATE KEY-----.*?-----END [A-Z0-9 ]*PRIVATE KEY-----".into(),
+        r"(?m)^[^:\s]+:(?P<secret>[^:\s]+)(?::\d*){6}:[^:\s]*$".into(),
+    ]
+}
+
+impl Default for TextDiffConfig {
+    fn default() -> Self {
+        TextDiffConfig {
+            max_file_size: default_text_diff_max_file_size(),
+            context_lines: default_text_diff_context(),
+            redact: default_redact(),
+        }
+    }
+}
+
+/// Change counts at which a job's Nagios status escalates. By default any change
+/// is CRITICAL.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct NagiosThresholds {
+    /// At least this many changes is WARNING
+    pub warning: usize,
+    /// At least this many changes is CRITICAL
+    pub critical: usize,
+}
+
+impl Default for NagiosThresholds {
+    fn default() -> Self {
+        NagiosThresholds {
+            warning: 1,
+            critical: 1,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct WatcherConfig {
     pub mode: String,
     pub poll_interval: Option<u64>,
+    /// Seconds between polls that hash every file, ignoring the hash cache (daily by
+    /// default; `null` never does)
+    #[serde(default = "default_full_rehash_interval")]
+    pub full_rehash_interval: Option<u64>,
+    /// Seconds between full verification passes in `hybrid` mode
+    pub rescan_interval: Option<u64>,
+    /// Cron expression (`minute hour day month weekday`, local time) of the runs of a
+    /// `scheduled` job
+    pub schedule: Option<String>,
+    /// Up to this many seconds of random delay before each scheduled run
+    pub jitter_secs: Option<u64>,
+    /// Run once right away if a scheduled run came due while the previous one was
+    /// still going, instead of skipping it
+    #[serde(default)]
+    pub catch_up: bool,
     pub debounce_ms: Option<u64>,
+    /// Which edge of a burst of inotify events on a path is handled
+    #[serde(default)]
+    pub debounce: DebounceMode,
+}
+
+fn default_full_rehash_interval() -> Option<u64> {
+    Some(86400)
 }
 
 impl Default for WatcherConfig {
@@ -39,11 +518,30 @@ impl Default for WatcherConfig {
         WatcherConfig {
             mode: "inotify".into(),
             poll_interval: Some(5),
+            full_rehash_interval: default_full_rehash_interval(),
+            rescan_interval: Some(3600),
+            schedule: None,
+            jitter_secs: None,
+            catch_up: false,
             debounce_ms: Some(500),
+            debounce: DebounceMode::default(),
         }
     }
 }
 
+/// When a path that keeps receiving events is hashed.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DebounceMode {
+    /// On the first event; further events within `debounce_ms` are dropped
+    Leading,
+    /// Once no event arrived for `debounce_ms`, so the final content is hashed
+    #[default]
+    Trailing,
+    /// On the first event, and again once quiet if more events followed
+    Both,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
 pub struct AlertsConfig {
     /// If set, send event via an HTTP POST (JSON) to this URL
@@ -60,6 +558,11 @@ pub struct AlertsConfig {
     pub plugin_path: Option<String>,
 
     /// An optional Liquid template (as a string) to render the JSON payload.
-    /// Available variables: `path`, `old`, `new`.
+    /// Available variables: `job_name`, `kind` (created/deleted/renamed/modified/
+    /// tampered/replaced/restored/degraded/summary), `path`, `from` (renames only),
+    /// `old`, `new`, `attributes` (changed attributes other than the content, as
+    /// "mode: 0644 -> 4755, …"), `diff` (unified diff of a text file), `findings`
+    /// ("severity: message; …") and `severity` (of the most severe finding).
+    /// Absent values render as "".
     pub payload_template: Option<String>,
 }
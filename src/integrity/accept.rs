@@ -0,0 +1,373 @@
+// Approving intended changes into a baseline (`watchdogfs accept`)
+use crate::config::{Config, JobConfig};
+use crate::integrity::signing::Keys;
+use crate::integrity::store::{self, ContentStore};
+use crate::integrity::{
+    Change, EntryOptions, WalkOptions, baseline_path, compare, history, lock_baseline,
+    read_baseline, save_baseline, scan,
+};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Where the acceptances of a job are logged, next to its baseline.
+pub fn audit_path(job_name: &str) -> PathBuf {
+    PathBuf::from(format!("audit_{}.jsonl", job_name))
+}
+
+/// One line of the audit log: who accepted which change, and when.
+#[derive(Debug, Serialize)]
+pub struct AuditRecord<'a> {
+    pub accepted_at: String,
+    /// Login name (`SUDO_USER`, `USER` or `LOGNAME`), as reported by the environment
+    pub user: String,
+    /// Real uid of the accepting process
+    pub uid: u32,
+    pub hostname: String,
+    pub job: &'a str,
+    pub kind: &'static str,
+    pub path: &'a str,
+    pub from: Option<&'a str>,
+    pub old: Option<&'a str>,
+    pub new: Option<&'a str>,
+}
+
+fn current_user() -> String {
+    ["SUDO_USER", "USER", "LOGNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))
+        .unwrap_or_default()
+}
+
+/// Append one record per accepted change to `audit_file`.
+fn write_audit(audit_file: &Path, job_name: &str, accepted: &[Change]) -> Result<()> {
+    let user = current_user();
+    // SAFETY: getuid has no preconditions and cannot fail
+    let uid = unsafe { libc::getuid() };
+    let hostname = hostname::get()
+        .map(|h| h.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let accepted_at = chrono::Utc::now().to_rfc3339();
+
+    let mut lines = String::new();
+    for change in accepted {
+        let record = AuditRecord {
+            accepted_at: accepted_at.clone(),
+            user: user.clone(),
+            uid,
+            hostname: hostname.clone(),
+            job: job_name,
+            kind: change.kind.as_str(),
+            path: &change.path,
+            from: change.from.as_deref(),
+            old: change.old_hash(),
+            new: change.new_hash(),
+        };
+        lines.push_str(&serde_json::to_string(&record)?);
+        lines.push('\n');
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(audit_file)
+        .and_then(|mut f| f.write_all(lines.as_bytes()))
+        .with_context(|| format!("appending to audit log {:?}", audit_file))
+}
+
+/// Compare the disk with the baseline of `job_cfg` in `baseline_file` and fold every
+/// pending change `choose` approves into it. Nothing else in the baseline moves, so
+/// unrelated tampering keeps alerting. The baseline is re-signed with `keys`, each
+/// accepted change is appended to `audit_file`, and the accepted changes are returned.
+/// The write and the audit happen under the baseline's lock (see `lock_baseline`).
+pub fn accept_job(
+    job_name: &str,
+    job_cfg: &JobConfig,
+    baseline_file: &Path,
+    audit_file: &Path,
+    keys: Option<&Keys>,
+    mut choose: impl FnMut(&Change) -> Result<bool>,
+) -> Result<Vec<Change>> {
+    let file = read_baseline(baseline_file, job_name, keys)
+        .with_context(|| format!("loading baseline for job '{}'", job_name))?;
+    let walk_opts = WalkOptions::for_job(job_cfg)
+        .with_context(|| format!("invalid ignore_patterns for job '{}'", job_name))?;
+    let current = scan(
+        &job_cfg.watch_paths,
+        &walk_opts,
+        &EntryOptions::for_job(job_cfg),
+        &file.entries,
+    )
+    .with_context(|| format!("scanning files of job '{}'", job_name))?;
+
+    let mut accepted = Vec::new();
+    for change in compare(&file.entries, &current, &job_cfg.attributes) {
+        if choose(&change)? {
+            accepted.push(change);
+        }
+    }
+    if accepted.is_empty() {
+        return Ok(accepted);
+    }
+
+    // The daemon may have persisted the baseline while we were asking; apply the
+    // accepted changes to what is on disk now, under the lock it takes too
+    let _lock = lock_baseline(baseline_file)?;
+    let mut baseline = read_baseline(baseline_file, job_name, keys)
+        .with_context(|| format!("reloading baseline for job '{}'", job_name))?
+        .entries;
+    for change in &accepted {
+        change.apply(&mut baseline);
+    }
+    save_baseline(baseline_file, &baseline, job_name, job_cfg, keys)
+        .with_context(|| format!("writing baseline for job '{}'", job_name))?;
+    write_audit(audit_file, job_name, &accepted)?;
+    Ok(accepted)
+}
+
+/// Whether `change` touches one of `paths` (a file, or a directory containing it).
+/// For renames both the old and the new path count.
+fn matches(change: &Change, paths: &[PathBuf]) -> bool {
+    let touched = std::iter::once(&change.path).chain(&change.from);
+    touched
+        .map(Path::new)
+        .any(|p| paths.iter().any(|wanted| p.starts_with(wanted)))
+}
+
+/// Ask about `change` on `output` and read the answer from `input`: `y` accepts,
+/// `q` (or end of input) rejects this and every remaining change, anything else
+/// rejects it.
+fn prompt(
+    change: &Change,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    quit: &mut bool,
+) -> Result<bool> {
+    if *quit {
+        return Ok(false);
+    }
+    write!(output, "{}\nAccept? [y/N/q] ", change.describe())?;
+    output.flush()?;
+    let mut answer = String::new();
+    if input.read_line(&mut answer)? == 0 {
+        writeln!(output)?;
+        *quit = true;
+        return Ok(false);
+    }
+    match answer.trim().to_ascii_lowercase().as_str() {
+        "y" | "yes" => Ok(true),
+        "q" | "quit" => {
+            *quit = true;
+            Ok(false)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Accept the pending changes of `job_name` under `paths`, or, with `interactive`,
+/// the ones approved one by one on the terminal. Paths are matched both as given
+/// and made absolute. Returns the accepted changes and the requested paths that
+/// had no pending change.
+pub fn run(
+    cfg: &Config,
+    job_name: &str,
+    paths: &[String],
+    interactive: bool,
+) -> Result<(Vec<Change>, Vec<String>)> {
+    let job_cfg = cfg
+        .jobs
+        .get(job_name)
+        .with_context(|| format!("No job named '{}' in the configuration", job_name))?;
+    let keys = Keys::for_config(cfg)?;
+
+    let wanted: Vec<Vec<PathBuf>> = paths
+        .iter()
+        .map(|p| {
+            let mut forms = vec![PathBuf::from(p)];
+            if let Ok(abs) = std::path::absolute(p) {
+                forms.push(abs);
+            }
+            forms
+        })
+        .collect();
+    let mut used = vec![false; wanted.len()];
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut quit = false;
+    let accepted = accept_job(
+        job_name,
+        job_cfg,
+        &baseline_path(job_name),
+        &audit_path(job_name),
+        keys.as_ref(),
+        |change| {
+            if interactive {
+                return prompt(change, &mut input, &mut io::stderr(), &mut quit);
+            }
+            let mut hit = false;
+            for (forms, used) in wanted.iter().zip(used.iter_mut()) {
+                if matches(change, forms) {
+                    *used = true;
+                    hit = true;
+                }
+            }
+            Ok(hit)
+        },
+    )?;
+
+    if !accepted.is_empty() {
+        if let Some(store) = ContentStore::for_job(cfg, job_cfg) {
+            for change in &accepted {
+                if let Some(entry) = &change.new
+                    && let Err(e) = store.put(&change.path, entry)
+                {
+                    eprintln!("Failed to store content of {}: {:?}", change.path, e);
+                }
+            }
+            store::prune_config(cfg).context("pruning the content store")?;
+        }
+        history::record(
+            &history::history_dir(job_name),
+            &baseline_path(job_name),
+            history::REASON_ACCEPT,
+            cfg.history_keep,
+        )
+        .with_context(|| format!("recording history for job '{}'", job_name))?;
+    }
+
+    let unmatched = paths
+        .iter()
+        .zip(&used)
+        .filter(|(_, used)| !interactive && !**used)
+        .map(|(p, _)| p.clone())
+        .collect();
+    Ok((accepted, unmatched))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accept_job, prompt};
+    use crate::config::JobConfig;
+    use crate::integrity::{
+        Change, ChangeKind, Entry, EntryOptions, WalkOptions, generate_map, load_baseline,
+        save_baseline,
+    };
+    use std::fs;
+    use std::io::Cursor;
+    use tempfile::tempdir;
+
+    #[test]
+    fn accepts_only_the_chosen_change_and_audits_it() {
+        let dir = tempdir().unwrap();
+        let conf = dir.path().join("nginx.conf");
+        let index = dir.path().join("index.php");
+        fs::write(&conf, "worker_processes 1;").unwrap();
+        fs::write(&index, "<?php echo 1;").unwrap();
+        let cfg = JobConfig {
+            watch_paths: vec![dir.path().to_str().unwrap().to_string()],
+            ..Default::default()
+        };
+        let state = tempdir().unwrap();
+        let file = state.path().join("baseline_web.json");
+        let audit = state.path().join("audit_web.jsonl");
+        let baseline = generate_map(
+            &cfg.watch_paths,
+            &WalkOptions::default(),
+            &EntryOptions::default(),
+        )
+        .unwrap();
+        save_baseline(&file, &baseline, "web", &cfg, None).unwrap();
+
+        fs::write(&conf, "worker_processes 4;").unwrap();
+        fs::write(&index, "<?php system($_GET['c']);").unwrap();
+        let conf = conf.to_str().unwrap().to_string();
+        let accepted =
+            accept_job("web", &cfg, &file, &audit, None, |c| Ok(c.path == conf)).unwrap();
+        assert_eq!(accepted.len(), 1);
+
+        let stored = load_baseline(&file, "web", None).unwrap();
+        let opts = EntryOptions::default();
+        assert_eq!(stored[&conf], Entry::from_path(&conf, &opts).unwrap());
+        let index = index.to_str().unwrap();
+        assert_eq!(stored[index], baseline[index]);
+
+        let log = fs::read_to_string(&audit).unwrap();
+        assert_eq!(log.lines().count(), 1);
+        let record: serde_json::Value = serde_json::from_str(log.trim()).unwrap();
+        assert_eq!(record["job"], "web");
+        assert_eq!(record["kind"], "modified");
+        assert_eq!(record["path"], conf.as_str());
+        assert!(record["accepted_at"].is_string());
+    }
+
+    #[test]
+    fn nothing_chosen_leaves_files_alone() {
+        let dir = tempdir().unwrap();
+        let cfg = JobConfig {
+            watch_paths: vec![dir.path().to_str().unwrap().to_string()],
+            ..Default::default()
+        };
+        let state = tempdir().unwrap();
+        let file = state.path().join("baseline_web.json");
+        let audit = state.path().join("audit_web.jsonl");
+        save_baseline(&file, &Default::default(), "web", &cfg, None).unwrap();
+        fs::write(dir.path().join("new.txt"), "x").unwrap();
+        let before = fs::read_to_string(&file).unwrap();
+
+        assert!(
+            accept_job("web", &cfg, &file, &audit, None, |_| Ok(false))
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(fs::read_to_string(&file).unwrap(), before);
+        assert!(!audit.exists());
+    }
+
+    #[test]
+    fn keeps_what_the_daemon_persisted_while_asking() {
+        let dir = tempdir().unwrap();
+        let conf = dir.path().join("nginx.conf");
+        fs::write(&conf, "worker_processes 1;").unwrap();
+        let cfg = JobConfig {
+            watch_paths: vec![dir.path().to_str().unwrap().to_string()],
+            ..Default::default()
+        };
+        let state = tempdir().unwrap();
+        let file = state.path().join("baseline_web.json");
+        let audit = state.path().join("audit_web.jsonl");
+        save_baseline(&file, &Default::default(), "web", &cfg, None).unwrap();
+
+        // The daemon records the new file while the operator is still deciding
+        let accepted = accept_job("web", &cfg, &file, &audit, None, |_| {
+            let mut persisted = load_baseline(&file, "web", None).unwrap();
+            persisted.insert("/var/www/index.php".into(), "aa".into());
+            save_baseline(&file, &persisted, "web", &cfg, None).unwrap();
+            Ok(true)
+        })
+        .unwrap();
+        assert_eq!(accepted.len(), 1);
+
+        let stored = load_baseline(&file, "web", None).unwrap();
+        assert!(stored.contains_key(conf.to_str().unwrap()));
+        assert!(stored.contains_key("/var/www/index.php"));
+        assert!(state.path().join("baseline_web.json.lock").exists());
+    }
+
+    #[test]
+    fn prompt_answers() {
+        let change = Change::deleted("/etc/motd".into(), Entry::from("00"));
+        assert_eq!(change.kind, ChangeKind::Deleted);
+        let mut out = Vec::new();
+        let mut quit = false;
+        let mut ask = |answers: &str, quit: &mut bool| {
+            prompt(&change, &mut Cursor::new(answers), &mut out, quit).unwrap()
+        };
+        assert!(ask("y\n", &mut quit));
+        assert!(!ask("\n", &mut quit));
+        assert!(!ask("q\n", &mut quit));
+        assert!(quit);
+        assert!(!ask("y\n", &mut quit));
+    }
+}
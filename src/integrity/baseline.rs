@@ -1,23 +1,314 @@
 // Baseline generation & validation
-use crate::integrity::calculate_checksum;
-use anyhow::Result;
-use std::collections::HashMap;
+use crate::config::JobConfig;
+use crate::integrity::hashcache::HashCache;
+use crate::integrity::signing::{Keys, Signature};
+use crate::integrity::{Entry, EntryOptions};
+use crate::integrity::walk::{WalkOptions, collect_files};
+use crate::utils::{lock_file, write_atomic};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-pub type Baseline = HashMap<String, String>;
+/// Path of each watched file mapped to what was recorded about it, sorted by path.
+pub type Baseline = BTreeMap<String, Entry>;
 
-pub fn generate(paths: &[String]) -> Result<Baseline> {
+/// Version of the on-disk format written by `save`. Version 1 was a bare
+/// path → entry map without a header.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Describes where and how a baseline file was produced.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    pub schema_version: u32,
+    /// Name of the job the baseline belongs to (absent in files from older versions)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub job: String,
+    /// Git commit of the watchdogfs build that wrote the file
+    pub tool_version: String,
+    pub hostname: String,
+    /// RFC 3339 timestamp of when the baseline was first written
+    pub created_at: String,
+    /// RFC 3339 timestamp of the latest write (absent in files from older versions)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub updated_at: String,
+    /// SHA-256 of the job configuration the entries were recorded under
+    pub job_config_hash: String,
+}
+
+impl Header {
+    /// A header for a baseline of job `job_name`, configured as `job_cfg`, written
+    /// now, on this host.
+    pub fn for_job(job_name: &str, job_cfg: &JobConfig) -> Self {
+        let now = chrono::Utc::now().to_rfc3339();
+        Header {
+            schema_version: SCHEMA_VERSION,
+            job: job_name.to_string(),
+            tool_version: env!("GIT_HASH").to_string(),
+            hostname: hostname::get()
+                .map(|h| h.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            created_at: now.clone(),
+            updated_at: now,
+            job_config_hash: config_hash(job_cfg),
+        }
+    }
+
+    /// What a version 1 file (no header) is read as.
+    fn legacy() -> Self {
+        Header {
+            schema_version: 1,
+            job: String::new(),
+            tool_version: String::new(),
+            hostname: String::new(),
+            created_at: String::new(),
+            updated_at: String::new(),
+            job_config_hash: String::new(),
+        }
+    }
+}
+
+/// Hex SHA-256 of the job configuration, serialized as JSON.
+pub fn config_hash(job_cfg: &JobConfig) -> String {
+    let json = serde_json::to_vec(job_cfg).unwrap_or_default();
+    format!("{:x}", Sha256::digest(&json))
+}
+
+/// A baseline file: header plus entries, optionally signed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BaselineFile {
+    pub header: Header,
+    pub entries: Baseline,
+    /// Covers `header` and `entries` (see `signed_bytes`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+}
+
+/// The part of a baseline file that is signed.
+#[derive(Serialize)]
+struct SignedPart<'a> {
+    header: &'a Header,
+    entries: &'a Baseline,
+}
+
+/// Canonical bytes a signature is computed over: compact JSON of the header and
+/// the (sorted) entries.
+fn signed_bytes(header: &Header, entries: &Baseline) -> Result<Vec<u8>> {
+    serde_json::to_vec(&SignedPart { header, entries }).context("serializing baseline")
+}
+
+impl BaselineFile {
+    /// Check the signature against `keys`, and that the (signed) header names
+    /// `job_name`, so the baseline of one job cannot stand in for another's.
+    pub fn verify(&self, keys: &Keys, job_name: &str) -> Result<()> {
+        self.verify_signature(keys)?;
+        if self.header.job != job_name {
+            anyhow::bail!(
+                "baseline is signed for job '{}', not '{}' (files signed by older versions \
+                 name no job; run `watchdogfs resign`)",
+                self.header.job,
+                job_name
+            );
+        }
+        Ok(())
+    }
+
+    /// Check only the signature, for files not known to belong to a job.
+    pub fn verify_signature(&self, keys: &Keys) -> Result<()> {
+        keys.verify(
+            &signed_bytes(&self.header, &self.entries)?,
+            self.signature.as_ref(),
+        )
+    }
+}
+
+/// Where the baseline of `job_name` is stored (`baseline_<job_name>.json` in the cwd).
+pub fn path_for(job_name: &str) -> PathBuf {
+    PathBuf::from(format!("baseline_{}.json", job_name))
+}
+
+/// Lock the baseline file at `path` against other writers until the returned file
+/// is dropped. `watchdogfs accept` and the daemon hold it from reading the baseline
+/// to writing it back, so neither overwrites what the other just recorded.
+pub fn lock(path: &Path) -> Result<fs::File> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    lock_file(PathBuf::from(lock_path))
+}
+
+/// Read the baseline file of `job_name`, header included, and verify it if `keys`
+/// is given. Files from before the header existed (schema version 1) are migrated
+/// in memory; `save` writes them back in the current format.
+pub fn read(path: &Path, job_name: &str, keys: Option<&Keys>) -> Result<BaselineFile> {
+    let file = read_unverified(path)?;
+    if let Some(keys) = keys {
+        file.verify(keys, job_name)
+            .with_context(|| format!("verifying signature of {:?}", path))?;
+    }
+    Ok(file)
+}
+
+/// Read a baseline file without checking its signature.
+pub fn read_unverified(path: &Path) -> Result<BaselineFile> {
+    let s = fs::read_to_string(path).with_context(|| format!("reading {:?}", path))?;
+    let value: serde_json::Value =
+        serde_json::from_str(&s).with_context(|| format!("parsing {:?}", path))?;
+    let versioned = value
+        .get("header")
+        .and_then(|h| h.get("schema_version"))
+        .is_some();
+    if !versioned {
+        let entries: Baseline =
+            serde_json::from_value(value).with_context(|| format!("parsing {:?}", path))?;
+        return Ok(BaselineFile {
+            header: Header::legacy(),
+            entries,
+            signature: None,
+        });
+    }
+
+    let file: BaselineFile =
+        serde_json::from_value(value).with_context(|| format!("parsing {:?}", path))?;
+    if file.header.schema_version > SCHEMA_VERSION {
+        anyhow::bail!(
+            "{:?} has baseline schema version {}, this build supports up to {}",
+            path,
+            file.header.schema_version,
+            SCHEMA_VERSION
+        );
+    }
+    Ok(file)
+}
+
+/// Read the entries of the baseline file of `job_name` written by `save` (or an
+/// older version), verifying it if `keys` is given.
+pub fn load(path: &Path, job_name: &str, keys: Option<&Keys>) -> Result<Baseline> {
+    Ok(read(path, job_name, keys)?.entries)
+}
+
+/// Write `baseline` of job `job_name` to `path` as an update of the baseline already
+/// there: the header is fresh except for `created_at`, which is kept.
+pub fn save(
+    path: &Path,
+    baseline: &Baseline,
+    job_name: &str,
+    job_cfg: &JobConfig,
+    keys: Option<&Keys>,
+) -> Result<()> {
+    let mut header = Header::for_job(job_name, job_cfg);
+    if let Ok(previous) = read_unverified(path)
+        && !previous.header.created_at.is_empty()
+    {
+        header.created_at = previous.header.created_at;
+    }
+    write(path, &header, baseline, keys)
+}
+
+/// Write `baseline` of job `job_name` to `path` as a new baseline, replacing any there.
+pub fn create(
+    path: &Path,
+    baseline: &Baseline,
+    job_name: &str,
+    job_cfg: &JobConfig,
+    keys: Option<&Keys>,
+) -> Result<()> {
+    write(path, &Header::for_job(job_name, job_cfg), baseline, keys)
+}
+
+/// Write a baseline file, signed with `keys` if given, atomically (temp file + fsync
+/// + rename), so a crash never leaves a truncated baseline behind.
+fn write(path: &Path, header: &Header, baseline: &Baseline, keys: Option<&Keys>) -> Result<()> {
+    #[derive(Serialize)]
+    struct FileRef<'a> {
+        header: &'a Header,
+        entries: &'a Baseline,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        signature: Option<Signature>,
+    }
+    let signature = match keys {
+        Some(keys) => Some(keys.sign(&signed_bytes(header, baseline)?)?),
+        None => None,
+    };
+    let json = serde_json::to_string_pretty(&FileRef {
+        header,
+        entries: baseline,
+        signature,
+    })
+    .context("serializing baseline")?;
+    write_atomic(path, json.as_bytes())
+}
+
+/// Hash and stat every regular file covered by `paths`. Directories are expanded
+/// recursively according to `opts`, producing one entry per file; `entry_opts`
+/// selects the optional properties recorded for each.
+pub fn generate(
+    paths: &[String],
+    opts: &WalkOptions,
+    entry_opts: &EntryOptions,
+) -> Result<Baseline> {
+    scan(paths, opts, entry_opts, &Baseline::new())
+}
+
+/// Like `generate`, but takes the entries of files whose stat is unchanged from
+/// `cache` instead of hashing them again. Cached files no longer found are dropped.
+pub fn generate_cached(
+    paths: &[String],
+    opts: &WalkOptions,
+    entry_opts: &EntryOptions,
+    cache: &mut HashCache,
+) -> Result<Baseline> {
     let mut baseline = Baseline::new();
-    for path in paths {
-        let checksum = calculate_checksum(path)?;
-        baseline.insert(path.clone(), checksum);
+    for path in collect_files(paths, opts)? {
+        if let Some(entry) = unless_vanished(&path, cache.entry(&path, entry_opts))? {
+            baseline.insert(path, entry);
+        }
     }
+    cache.retain(|path| baseline.contains_key(path));
     Ok(baseline)
 }
 
+/// Like `generate`, but also computes every digest `previous` records for a path,
+/// so the result can be compared against `previous` even if the job's
+/// `hash_algorithms` changed since it was written.
+pub fn scan(
+    paths: &[String],
+    opts: &WalkOptions,
+    entry_opts: &EntryOptions,
+    previous: &Baseline,
+) -> Result<Baseline> {
+    let mut baseline = Baseline::new();
+    for path in collect_files(paths, opts)? {
+        let entry = match previous.get(&path) {
+            Some(old) => Entry::from_path(&path, &entry_opts.covering(old)),
+            None => Entry::from_path(&path, entry_opts),
+        };
+        if let Some(entry) = unless_vanished(&path, entry)? {
+            baseline.insert(path, entry);
+        }
+    }
+    Ok(baseline)
+}
+
+/// `None` if reading `path` failed because it does not exist (a watch path that is
+/// gone, or a file deleted during the walk), so it is left out like any deleted
+/// file; other errors are returned.
+fn unless_vanished(path: &str, entry: Result<Entry>) -> Result<Option<Entry>> {
+    match entry {
+        Ok(entry) => Ok(Some(entry)),
+        Err(_) if !Path::new(path).exists() => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::generate;
-    use std::fs::File;
+    use super::{SCHEMA_VERSION, create, generate, load, read, save};
+    use crate::config::{JobConfig, SigningConfig, SigningMethod};
+    use crate::integrity::signing::{Keys, generate_key};
+    use crate::integrity::{Entry, EntryOptions, WalkOptions};
+    use std::fs::{self, File};
     use std::io::Write;
     use tempfile::tempdir;
 
@@ -37,10 +328,126 @@ mod tests {
             a.to_str().unwrap().to_string(),
             b.to_str().unwrap().to_string(),
         ];
-        let baseline = generate(&paths).unwrap();
+        let baseline = generate(&paths, &WalkOptions::default(), &EntryOptions::default()).unwrap();
         assert_eq!(baseline.len(), 2);
         // Ensure keys match
         assert!(baseline.contains_key(&paths[0]));
         assert!(baseline.contains_key(&paths[1]));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn baseline_directory_has_one_entry_per_file() {
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join("nested")).unwrap();
+        fs::write(dir.path().join("index.html"), "home").unwrap();
+        fs::write(dir.path().join("nested/page.html"), "page").unwrap();
+
+        let paths = vec![dir.path().to_str().unwrap().to_string()];
+        let baseline = generate(&paths, &WalkOptions::default(), &EntryOptions::default()).unwrap();
+        assert_eq!(baseline.len(), 2);
+        let nested = dir.path().join("nested/page.html");
+        assert!(baseline.contains_key(nested.to_str().unwrap()));
+    }
+
+    #[test]
+    fn save_then_load_roundtrip() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("baseline_job.json");
+        let watched = dir.path().join("group");
+        fs::write(&watched, "root:x:0:").unwrap();
+        let mut baseline = super::Baseline::new();
+        baseline.insert("/etc/passwd".into(), "abc".into());
+        baseline.insert(
+            "/etc/group".into(),
+            Entry::from_path(watched.to_str().unwrap(), &EntryOptions::default()).unwrap(),
+        );
+
+        let cfg = JobConfig::default();
+        save(&file, &baseline, "job", &cfg, None).unwrap();
+        assert_eq!(load(&file, "job", None).unwrap(), baseline);
+
+        let read_back = read(&file, "job", None).unwrap();
+        assert_eq!(read_back.header.schema_version, SCHEMA_VERSION);
+        assert_eq!(read_back.header.tool_version, env!("GIT_HASH"));
+        assert_eq!(read_back.header.job_config_hash, super::config_hash(&cfg));
+        // Entries are written in path order
+        let json = fs::read_to_string(&file).unwrap();
+        assert!(json.find("/etc/group").unwrap() < json.find("/etc/passwd").unwrap());
+    }
+
+    #[test]
+    fn updates_keep_the_creation_time() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("baseline_job.json");
+        let cfg = JobConfig::default();
+        create(&file, &super::Baseline::new(), "job", &cfg, None).unwrap();
+        let created = read(&file, "job", None).unwrap().header;
+        assert_eq!(created.updated_at, created.created_at);
+
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        save(&file, &super::Baseline::new(), "job", &cfg, None).unwrap();
+        let updated = read(&file, "job", None).unwrap().header;
+        assert_eq!(updated.created_at, created.created_at);
+        assert!(updated.updated_at > created.updated_at);
+
+        create(&file, &super::Baseline::new(), "job", &cfg, None).unwrap();
+        assert!(read(&file, "job", None).unwrap().header.created_at > created.created_at);
+    }
+
+    #[test]
+    fn newer_schema_is_rejected() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("baseline_new.json");
+        let future = format!(
+            r#"{{"header": {{"schema_version": {}, "tool_version": "", "hostname": "",
+                 "created_at": "", "job_config_hash": ""}}, "entries": {{}}}}"#,
+            SCHEMA_VERSION + 1
+        );
+        fs::write(&file, future).unwrap();
+        let err = read(&file, "job", None).unwrap_err();
+        assert!(err.to_string().contains("schema version"));
+    }
+
+    #[test]
+    fn load_accepts_hash_only_entries() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("baseline_old.json");
+        fs::write(&file, r#"{"/etc/passwd": "abc"}"#).unwrap();
+        let file_read = read(&file, "job", None).unwrap();
+        assert_eq!(file_read.header.schema_version, 1);
+        assert_eq!(file_read.entries["/etc/passwd"], Entry::from("abc"));
+    }
+
+    #[test]
+    fn signed_baseline_detects_edits() {
+        let dir = tempdir().unwrap();
+        let key = dir.path().join("hmac.key");
+        generate_key(SigningMethod::HmacSha256, &key).unwrap();
+        let keys = Keys::load(&SigningConfig {
+            method: SigningMethod::HmacSha256,
+            key_path: Some(key.to_str().unwrap().into()),
+            public_key_path: None,
+        })
+        .unwrap();
+
+        let file = dir.path().join("baseline_job.json");
+        let mut baseline = super::Baseline::new();
+        baseline.insert("/etc/passwd".into(), "abc".into());
+        save(&file, &baseline, "job", &JobConfig::default(), Some(&keys)).unwrap();
+        assert_eq!(load(&file, "job", Some(&keys)).unwrap(), baseline);
+
+        // An attacker "accepts" their change by rewriting the recorded hash
+        let json = fs::read_to_string(&file).unwrap().replace("\"abc\"", "\"evil\"");
+        fs::write(&file, json).unwrap();
+        let err = load(&file, "job", Some(&keys)).unwrap_err();
+        assert!(format!("{:#}", err).contains("does not match"));
+        // Unsigned files are refused once signing is configured
+        save(&file, &baseline, "job", &JobConfig::default(), None).unwrap();
+        assert!(load(&file, "job", Some(&keys)).is_err());
+        // A validly signed baseline of another job is refused too
+        save(&file, &baseline, "db", &JobConfig::default(), Some(&keys)).unwrap();
+        assert_eq!(load(&file, "db", Some(&keys)).unwrap(), baseline);
+        let err = load(&file, "job", Some(&keys)).unwrap_err();
+        assert!(format!("{:#}", err).contains("signed for job 'db'"));
+    }
+}
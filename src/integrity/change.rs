@@ -0,0 +1,410 @@
+// Classification of differences between two baselines
+use crate::config::Attribute;
+use crate::integrity::semantic::Finding;
+use crate::integrity::{AttributeChange, Baseline, Entry};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// What happened to a watched path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    /// A file appeared that is not in the baseline.
+    Created,
+    /// A baseline file no longer exists.
+    Deleted,
+    /// A baseline file moved to a new path (`from` holds the old path).
+    Renamed,
+    /// A baseline file still exists but some compared attribute changed.
+    Modified,
+    /// The baseline file itself (`path`) failed signature verification.
+    Tampered,
+    /// A modified or deleted file was written back from the content store.
+    Restored,
+    /// A baseline file was atomically replaced by another file renamed over it
+    /// (`from` holds that file's path, if seen).
+    Replaced,
+    /// The job ran out of inotify watches and fell back to polling (`path` is where a
+    /// watch could not be added).
+    Degraded,
+    /// The outcome of a scheduled scan (`path` lists the watch paths).
+    Summary,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Deleted => "deleted",
+            ChangeKind::Renamed => "renamed",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Tampered => "tampered",
+            ChangeKind::Restored => "restored",
+            ChangeKind::Replaced => "replaced",
+            ChangeKind::Degraded => "degraded",
+            ChangeKind::Summary => "summary",
+        }
+    }
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A single detected change, as handed to `alerts::dispatch`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// The affected path (the new path for renames)
+    pub path: String,
+    /// The previous path, for renames and replacements only
+    pub from: Option<String>,
+    /// Baseline entry (absent for creations)
+    pub old: Option<Entry>,
+    /// Entry now on disk (absent for deletions)
+    pub new: Option<Entry>,
+    /// Compared attributes that differ (modifications only)
+    pub attributes: Vec<AttributeChange>,
+    /// Unified diff of the (redacted) old and new content, for text files of jobs
+    /// with `text_diff`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    /// What the change means for a well-known file, most severe first (jobs with
+    /// `semantic_findings`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub findings: Vec<Finding>,
+}
+
+impl Change {
+    pub fn created(path: String, new: Entry) -> Self {
+        Change {
+            kind: ChangeKind::Created,
+            path,
+            from: None,
+            old: None,
+            new: Some(new),
+            attributes: Vec::new(),
+            diff: None,
+            findings: Vec::new(),
+        }
+    }
+
+    pub fn deleted(path: String, old: Entry) -> Self {
+        Change {
+            kind: ChangeKind::Deleted,
+            path,
+            from: None,
+            old: Some(old),
+            new: None,
+            attributes: Vec::new(),
+            diff: None,
+            findings: Vec::new(),
+        }
+    }
+
+    pub fn renamed(from: String, path: String, old: Entry, new: Entry) -> Self {
+        Change {
+            kind: ChangeKind::Renamed,
+            path,
+            from: Some(from),
+            old: Some(old),
+            new: Some(new),
+            attributes: Vec::new(),
+            diff: None,
+            findings: Vec::new(),
+        }
+    }
+
+    pub fn modified(
+        path: String,
+        old: Entry,
+        new: Entry,
+        attributes: Vec<AttributeChange>,
+    ) -> Self {
+        Change {
+            kind: ChangeKind::Modified,
+            path,
+            from: None,
+            old: Some(old),
+            new: Some(new),
+            attributes,
+            diff: None,
+            findings: Vec::new(),
+        }
+    }
+
+    /// The baseline stored at `path` did not verify.
+    pub fn tampered(path: String) -> Self {
+        Change {
+            kind: ChangeKind::Tampered,
+            path,
+            from: None,
+            old: None,
+            new: None,
+            attributes: Vec::new(),
+            diff: None,
+            findings: Vec::new(),
+        }
+    }
+
+    /// `path` was restored to `restored`, replacing `tampered` (absent if it was deleted).
+    pub fn restored(path: String, tampered: Option<Entry>, restored: Entry) -> Self {
+        Change {
+            kind: ChangeKind::Restored,
+            path,
+            from: None,
+            old: tampered,
+            new: Some(restored),
+            attributes: Vec::new(),
+            diff: None,
+            findings: Vec::new(),
+        }
+    }
+
+    /// The job watching `path` degraded to polling, for the reason in `warning`.
+    pub fn degraded(path: String, warning: Finding) -> Self {
+        Change {
+            kind: ChangeKind::Degraded,
+            path,
+            from: None,
+            old: None,
+            new: None,
+            attributes: Vec::new(),
+            diff: None,
+            findings: vec![warning],
+        }
+    }
+
+    /// A scheduled scan of `paths` finished, as told by `outcome`.
+    pub fn summary(paths: String, outcome: Finding) -> Self {
+        Change {
+            kind: ChangeKind::Summary,
+            path: paths,
+            from: None,
+            old: None,
+            new: None,
+            attributes: Vec::new(),
+            diff: None,
+            findings: vec![outcome],
+        }
+    }
+
+    /// Report a modification as `path` having been replaced by the file renamed over
+    /// it from `from`. Other kinds are returned unchanged.
+    pub fn into_replaced(self, from: Option<String>) -> Self {
+        if self.kind != ChangeKind::Modified {
+            return self;
+        }
+        Change {
+            kind: ChangeKind::Replaced,
+            from,
+            ..self
+        }
+    }
+
+    /// Checksum recorded in the baseline, if any
+    pub fn old_hash(&self) -> Option<&str> {
+        self.old.as_ref().and_then(Entry::digest)
+    }
+
+    /// Checksum now on disk, if any
+    pub fn new_hash(&self) -> Option<&str> {
+        self.new.as_ref().and_then(Entry::digest)
+    }
+
+    /// Kind and path, plus one indented line per differing attribute.
+    pub fn describe(&self) -> String {
+        let mut line = format!("{:<9} {}", self.kind.as_str(), self.path);
+        if let Some(from) = &self.from {
+            line.push_str(&format!(" (from {})", from));
+        }
+        for attr in &self.attributes {
+            line.push_str(&format!(
+                "\n          {}: {} -> {}",
+                attr.attribute.as_str(),
+                attr.old,
+                attr.new
+            ));
+        }
+        line
+    }
+
+    /// Bring `baseline` in line with this change.
+    pub fn apply(&self, baseline: &mut Baseline) {
+        if let Some(from) = &self.from {
+            baseline.remove(from);
+        }
+        match &self.new {
+            Some(entry) => {
+                baseline.insert(self.path.clone(), entry.clone());
+            }
+            None => {
+                baseline.remove(&self.path);
+            }
+        }
+    }
+}
+
+/// Compare a stored baseline against a freshly computed one, looking only at
+/// the given `attrs` for files present in both.
+///
+/// A deleted and a created path with the same checksum are reported as one
+/// rename. The result is sorted by path.
+pub fn compare(old: &Baseline, new: &Baseline, attrs: &[Attribute]) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut deleted: BTreeMap<&String, &Entry> = BTreeMap::new();
+    let mut created: BTreeMap<&String, &Entry> = BTreeMap::new();
+
+    for (path, old_entry) in old {
+        match new.get(path) {
+            Some(new_entry) => {
+                let diff = old_entry.diff(new_entry, attrs);
+                if !diff.is_empty() {
+                    changes.push(Change::modified(
+                        path.clone(),
+                        old_entry.clone(),
+                        new_entry.clone(),
+                        diff,
+                    ));
+                }
+            }
+            None => {
+                deleted.insert(path, old_entry);
+            }
+        }
+    }
+    for (path, new_entry) in new {
+        if !old.contains_key(path) {
+            created.insert(path, new_entry);
+        }
+    }
+
+    // Pair up deletions and creations that carry identical content
+    for (from, old_entry) in deleted {
+        let target = created
+            .iter()
+            .find(|(_, created_entry)| created_entry.same_content(old_entry))
+            .map(|(path, entry)| (*path, *entry));
+        match target {
+            Some((to, new_entry)) => {
+                created.remove(to);
+                changes.push(Change::renamed(
+                    from.clone(),
+                    to.clone(),
+                    old_entry.clone(),
+                    new_entry.clone(),
+                ));
+            }
+            None => changes.push(Change::deleted(from.clone(), old_entry.clone())),
+        }
+    }
+    for (path, entry) in created {
+        changes.push(Change::created(path.clone(), entry.clone()));
+    }
+
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChangeKind, compare};
+    use crate::config::{Attribute, default_attributes};
+    use crate::integrity::{Baseline, Entry};
+
+    fn baseline(entries: &[(&str, &str)]) -> Baseline {
+        entries
+            .iter()
+            .map(|(p, s)| (p.to_string(), Entry::from(*s)))
+            .collect()
+    }
+
+    #[test]
+    fn identical_baselines_have_no_changes() {
+        let a = baseline(&[("/a", "1"), ("/b", "2")]);
+        assert!(compare(&a, &a.clone(), &default_attributes()).is_empty());
+    }
+
+    #[test]
+    fn classifies_created_deleted_modified() {
+        let old = baseline(&[("/keep", "1"), ("/gone", "2"), ("/edit", "3")]);
+        let new = baseline(&[("/keep", "1"), ("/edit", "4"), ("/shell.php", "5")]);
+        let changes = compare(&old, &new, &default_attributes());
+        let kinds: Vec<_> = changes.iter().map(|c| (c.path.as_str(), c.kind)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("/edit", ChangeKind::Modified),
+                ("/gone", ChangeKind::Deleted),
+                ("/shell.php", ChangeKind::Created),
+            ]
+        );
+    }
+
+    #[test]
+    fn same_content_at_new_path_is_a_rename() {
+        let old = baseline(&[("/etc/old.conf", "abc")]);
+        let new = baseline(&[("/etc/new.conf", "abc")]);
+        let changes = compare(&old, &new, &default_attributes());
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, ChangeKind::Renamed);
+        assert_eq!(changes[0].from.as_deref(), Some("/etc/old.conf"));
+        assert_eq!(changes[0].path, "/etc/new.conf");
+    }
+
+    #[test]
+    fn apply_brings_baseline_in_line() {
+        let old = baseline(&[("/a", "1"), ("/b", "2")]);
+        let new = baseline(&[("/a", "9"), ("/c", "2"), ("/d", "4")]);
+        let mut applied = old.clone();
+        for change in compare(&old, &new, &default_attributes()) {
+            change.apply(&mut applied);
+        }
+        assert_eq!(applied, new);
+    }
+
+    #[test]
+    fn metadata_only_change_lists_attributes() {
+        let mut old = baseline(&[("/etc/passwd", "abc")]);
+        let mut new = old.clone();
+        old.get_mut("/etc/passwd").unwrap().mode = Some(0o644);
+        new.get_mut("/etc/passwd").unwrap().mode = Some(0o666);
+
+        let changes = compare(&old, &new, &default_attributes());
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, ChangeKind::Modified);
+        assert_eq!(changes[0].attributes.len(), 1);
+        assert_eq!(changes[0].attributes[0].attribute, Attribute::Mode);
+
+        // Not compared unless selected
+        assert!(compare(&old, &new, &[Attribute::Content]).is_empty());
+    }
+
+    #[test]
+    fn replaced_modification_forgets_the_temp_file() {
+        let old = baseline(&[("/etc/hosts", "1"), ("/etc/.hosts.swp", "2")]);
+        let new = baseline(&[("/etc/hosts", "2")]);
+        let mut applied = old.clone();
+        let changes: Vec<_> = compare(&old, &new, &default_attributes())
+            .into_iter()
+            .map(|c| c.into_replaced(Some("/etc/.hosts.swp".into())))
+            .collect();
+        // The temp file's disappearance is not a rename, as its target existed
+        let kinds: Vec<_> = changes.iter().map(|c| (c.path.as_str(), c.kind)).collect();
+        assert_eq!(
+            kinds,
+            [
+                ("/etc/.hosts.swp", ChangeKind::Deleted),
+                ("/etc/hosts", ChangeKind::Replaced),
+            ]
+        );
+        assert_eq!(changes[1].from.as_deref(), Some("/etc/.hosts.swp"));
+        for change in &changes {
+            change.apply(&mut applied);
+        }
+        assert_eq!(applied, new);
+    }
+}
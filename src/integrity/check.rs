@@ -0,0 +1,281 @@
+// One-shot comparison of the disk against the stored baselines (`watchdogfs check`)
+use crate::config::{Config, JobConfig};
+use crate::integrity::signing::Keys;
+use crate::integrity::{
+    Change, EntryOptions, WalkOptions, baseline_path, compare, read_baseline, scan,
+};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Exit code when every checked job matches its baseline.
+pub const EXIT_CLEAN: i32 = 0;
+/// Exit code when at least one job has changes (and none failed).
+pub const EXIT_CHANGED: i32 = 1;
+/// Exit code when a job could not be checked (missing or tampered baseline, I/O error, …).
+pub const EXIT_ERROR: i32 = 2;
+
+/// Outcome of checking one job, ordered from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Clean,
+    Changed,
+    Error,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Clean => "clean",
+            Status::Changed => "changed",
+            Status::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobReport {
+    pub job: String,
+    pub status: Status,
+    /// Files on disk that were compared
+    pub files: usize,
+    pub changes: Vec<Change>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl JobReport {
+    fn failed(job: &str, error: anyhow::Error) -> Self {
+        JobReport {
+            job: job.to_string(),
+            status: Status::Error,
+            files: 0,
+            changes: Vec::new(),
+            error: Some(format!("{:#}", error)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// Worst status over all jobs
+    pub status: Status,
+    pub jobs: Vec<JobReport>,
+}
+
+/// Compare the disk against the baseline of `job_cfg` stored in `baseline_file`,
+/// without modifying anything. Signed baselines are verified with `keys`.
+pub fn check_job(
+    job_name: &str,
+    job_cfg: &JobConfig,
+    baseline_file: &Path,
+    keys: Option<&Keys>,
+) -> Result<JobReport> {
+    let file = read_baseline(baseline_file, job_name, keys)
+        .with_context(|| format!("loading baseline for job '{}'", job_name))?;
+    let walk_opts = WalkOptions::for_job(job_cfg)
+        .with_context(|| format!("invalid ignore_patterns for job '{}'", job_name))?;
+    let current = scan(
+        &job_cfg.watch_paths,
+        &walk_opts,
+        &EntryOptions::for_job(job_cfg),
+        &file.entries,
+    )
+    .with_context(|| format!("scanning files of job '{}'", job_name))?;
+    let changes = compare(&file.entries, &current, &job_cfg.attributes);
+    Ok(JobReport {
+        job: job_name.to_string(),
+        status: if changes.is_empty() {
+            Status::Clean
+        } else {
+            Status::Changed
+        },
+        files: current.len(),
+        changes,
+        error: None,
+    })
+}
+
+/// Check every job of `cfg`, or only `only` if given. A job that cannot be checked
+/// is reported as an error without stopping the others.
+pub fn run(cfg: &Config, only: Option<&str>) -> Result<Report> {
+    if let Some(name) = only
+        && !cfg.jobs.contains_key(name)
+    {
+        anyhow::bail!("No job named '{}' in the configuration", name);
+    }
+    let keys = Keys::for_config(cfg)?;
+
+    let mut names: Vec<&String> = cfg
+        .jobs
+        .keys()
+        .filter(|n| only.is_none_or(|o| o == *n))
+        .collect();
+    names.sort();
+
+    let jobs: Vec<JobReport> = names
+        .into_iter()
+        .map(|name| {
+            check_job(name, &cfg.jobs[name], &baseline_path(name), keys.as_ref())
+                .unwrap_or_else(|e| JobReport::failed(name, e))
+        })
+        .collect();
+    let status = jobs.iter().map(|j| j.status).max().unwrap_or(Status::Clean);
+    Ok(Report { status, jobs })
+}
+
+impl Report {
+    pub fn exit_code(&self) -> i32 {
+        match self.status {
+            Status::Clean => EXIT_CLEAN,
+            Status::Changed => EXIT_CHANGED,
+            Status::Error => EXIT_ERROR,
+        }
+    }
+
+    /// Plain-text report, one line per change.
+    pub fn to_human(&self) -> String {
+        let mut out = String::new();
+        for job in &self.jobs {
+            match (&job.error, job.changes.len()) {
+                (Some(e), _) => {
+                    let _ = writeln!(out, "✗ {}: error: {}", job.job, e);
+                }
+                (None, 0) => {
+                    let _ = writeln!(out, "✓ {}: clean", job.job);
+                }
+                (None, n) => {
+                    let _ = writeln!(out, "✗ {}: {} change(s)", job.job, n);
+                }
+            }
+            for change in &job.changes {
+                for line in change.describe().lines() {
+                    let _ = writeln!(out, "    {}", line);
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EXIT_CHANGED, EXIT_CLEAN, EXIT_ERROR, JobReport, Report, Status, check_job};
+    use crate::config::{Attribute, JobConfig};
+    use crate::integrity::{
+        AttributeChange, Change, ChangeKind, Entry, EntryOptions, WalkOptions, generate_map,
+        save_baseline,
+    };
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn reports_changes_without_touching_the_baseline() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("index.php"), "<?php echo 1;").unwrap();
+        let cfg = JobConfig {
+            watch_paths: vec![dir.path().to_str().unwrap().to_string()],
+            ..Default::default()
+        };
+        let state = tempdir().unwrap();
+        let file = state.path().join("baseline_web.json");
+        let baseline = generate_map(
+            &cfg.watch_paths,
+            &WalkOptions::default(),
+            &EntryOptions::default(),
+        )
+        .unwrap();
+        save_baseline(&file, &baseline, "web", &cfg, None).unwrap();
+
+        let clean = check_job("web", &cfg, &file, None).unwrap();
+        assert_eq!((clean.status, clean.files), (Status::Clean, 1));
+
+        fs::write(dir.path().join("shell.php"), "<?php system($_GET['c']);").unwrap();
+        let changes = check_job("web", &cfg, &file, None).unwrap().changes;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, ChangeKind::Created);
+        assert!(!fs::read_to_string(&file).unwrap().contains("shell.php"));
+    }
+
+    #[test]
+    fn deleted_watch_path_is_reported() {
+        let dir = tempdir().unwrap();
+        let passwd = dir.path().join("passwd");
+        fs::write(&passwd, "root:x:0:0::/root:/bin/sh").unwrap();
+        let cfg = JobConfig {
+            watch_paths: vec![passwd.to_str().unwrap().to_string()],
+            ..Default::default()
+        };
+        let state = tempdir().unwrap();
+        let file = state.path().join("baseline_etc.json");
+        let baseline = generate_map(
+            &cfg.watch_paths,
+            &WalkOptions::default(),
+            &EntryOptions::default(),
+        )
+        .unwrap();
+        save_baseline(&file, &baseline, "etc", &cfg, None).unwrap();
+
+        fs::remove_file(&passwd).unwrap();
+        let report = check_job("etc", &cfg, &file, None).unwrap();
+        assert_eq!(report.status, Status::Changed);
+        assert_eq!(report.changes.len(), 1);
+        assert_eq!(report.changes[0].kind, ChangeKind::Deleted);
+    }
+
+    #[test]
+    fn missing_baseline_is_an_error() {
+        let state = tempdir().unwrap();
+        let file = state.path().join("baseline_gone.json");
+        let err = check_job("gone", &JobConfig::default(), &file, None).unwrap_err();
+        assert!(format!("{:#}", err).contains("loading baseline"));
+    }
+
+    #[test]
+    fn exit_codes_follow_status() {
+        let report = |status| Report {
+            status,
+            jobs: Vec::new(),
+        };
+        assert_eq!(report(Status::Clean).exit_code(), EXIT_CLEAN);
+        assert_eq!(report(Status::Changed).exit_code(), EXIT_CHANGED);
+        assert_eq!(report(Status::Error).exit_code(), EXIT_ERROR);
+    }
+
+    #[test]
+    fn human_report_indents_each_change_line() {
+        let mode = AttributeChange {
+            attribute: Attribute::Mode,
+            old: "0644".into(),
+            new: "4755".into(),
+        };
+        let change = Change::modified(
+            "/usr/bin/su".into(),
+            Entry::from("1"),
+            Entry::from("1"),
+            vec![mode],
+        );
+        let report = Report {
+            status: Status::Changed,
+            jobs: vec![JobReport {
+                job: "bin".into(),
+                status: Status::Changed,
+                files: 1,
+                changes: vec![change.clone()],
+                error: None,
+            }],
+        };
+        let expected: String = change
+            .describe()
+            .lines()
+            .map(|line| format!("    {}\n", line))
+            .collect();
+        assert_eq!(
+            report.to_human(),
+            format!("✗ bin: 1 change(s)\n{}", expected)
+        );
+        assert!(expected.contains("mode: 0644 -> 4755"));
+    }
+}
@@ -1,19 +1,149 @@
-// SHA256 checksum logic
-use sha2::{Sha256, Digest};
-use std::fs;
-use anyhow::Result;
+// Content checksums (SHA-256, SHA-512, BLAKE3), streamed through a fixed-size buffer
+use crate::config::HashAlgorithm;
+use sha2::{Sha256, Sha512, Digest};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use anyhow::{Context, Result};
 
-pub fn calculate_checksum(path: &str) -> Result<String> {
-    let data = fs::read(path)?;
-    let mut hasher = Sha256::new();
-    hasher.update(&data);
-    Ok(format!("{:x}", hasher.finalize()))
+/// Hex digests of one file, keyed by the algorithm that produced them.
+pub type Digests = BTreeMap<HashAlgorithm, String>;
+
+/// Bytes read per chunk; memory use does not grow with the file size.
+const BUF_SIZE: usize = 64 * 1024;
+
+/// Bytes hashed at each end of a file under the `head-tail` large-file policy.
+pub const HEAD_TAIL_BYTES: u64 = 1024 * 1024;
+
+/// How much of a file its digests cover.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HashScope {
+    /// The whole content
+    #[default]
+    Full,
+    /// Only the first and the last `n` bytes
+    HeadTail(u64),
+    /// Not hashed at all (too large)
+    Skipped,
+}
+
+impl HashScope {
+    pub fn is_full(&self) -> bool {
+        *self == HashScope::Full
+    }
+}
+
+/// One running digest computation.
+enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(alg: HashAlgorithm) -> Self {
+        match alg {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Sha512(h) => h.update(data),
+            Hasher::Blake3(h) => {
+                h.update(data);
+            }
+        }
+    }
+
+    fn finalize(self) -> String {
+        match self {
+            Hasher::Sha256(h) => format!("{:x}", h.finalize()),
+            Hasher::Sha512(h) => format!("{:x}", h.finalize()),
+            Hasher::Blake3(h) => h.finalize().to_hex().to_string(),
+        }
+    }
 }
 
+/// Feed everything `reader` yields to all `hashers`, one buffer at a time.
+fn stream(mut reader: impl Read, hashers: &mut [Hasher]) -> io::Result<()> {
+    let mut buf = vec![0u8; BUF_SIZE];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for h in hashers.iter_mut() {
+            h.update(&buf[..n]);
+        }
+    }
+}
+
+/// Hex digest with `algorithm` of everything `reader` yields.
+pub fn hash_reader(reader: impl Read, algorithm: HashAlgorithm) -> io::Result<String> {
+    let mut hashers = [Hasher::new(algorithm)];
+    stream(reader, &mut hashers)?;
+    let [hasher] = hashers;
+    Ok(hasher.finalize())
+}
+
+/// Hex SHA-256 of the file at `path`.
+pub fn calculate_checksum<P: AsRef<Path>>(path: P) -> Result<String> {
+    let path = path.as_ref();
+    let digests = hash_file(path, &[HashAlgorithm::Sha256], HashScope::Full)?;
+    digests
+        .into_values()
+        .next()
+        .with_context(|| format!("no digest computed for {}", path.display()))
+}
+
+/// Digest `path` with each of `algorithms`, reading the file only once.
+pub fn calculate_digests(path: &str, algorithms: &[HashAlgorithm]) -> Result<Digests> {
+    hash_file(Path::new(path), algorithms, HashScope::Full)
+}
+
+/// Digest the part of `path` selected by `scope` with each of `algorithms`.
+/// `HashScope::Skipped` opens nothing and yields no digests.
+pub fn hash_file(path: &Path, algorithms: &[HashAlgorithm], scope: HashScope) -> Result<Digests> {
+    if scope == HashScope::Skipped {
+        return Ok(Digests::new());
+    }
+    let mut file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut hashers: Vec<Hasher> = algorithms.iter().map(|&alg| Hasher::new(alg)).collect();
+    let result = match scope {
+        HashScope::HeadTail(n) => {
+            // Head, then the last `n` bytes (without hashing any byte twice)
+            let len = file.metadata()?.len();
+            stream((&mut file).take(n), &mut hashers).and_then(|()| {
+                if len > n {
+                    file.seek(SeekFrom::Start(n.max(len - n)))?;
+                    stream(&mut file, &mut hashers)?;
+                }
+                Ok(())
+            })
+        }
+        _ => stream(&mut file, &mut hashers),
+    };
+    result.with_context(|| format!("reading {}", path.display()))?;
+    Ok(algorithms
+        .iter()
+        .copied()
+        .zip(hashers.into_iter().map(Hasher::finalize))
+        .collect())
+}
 
 #[cfg(test)]
 mod tests {
-    use super::calculate_checksum;
+    use super::{BUF_SIZE, HashScope, calculate_checksum, calculate_digests, hash_file};
+    use crate::config::HashAlgorithm;
     use std::fs;
     use std::io::Write;
     use tempfile::NamedTempFile;
@@ -22,7 +152,7 @@ mod tests {
     fn checksum_empty_file() {
         let tmp = NamedTempFile::new().unwrap();
         // ensure file exists on disk
-        fs::write(tmp.path(), &[]).unwrap();
+        fs::write(tmp.path(), []).unwrap();
         let sum = calculate_checksum(tmp.path().to_str().unwrap()).unwrap();
         // SHA256 of empty content
         assert_eq!(
@@ -43,4 +173,49 @@ mod tests {
              c484efe37a5380ee9088f7ace2efcde9"
         );
     }
+
+    #[test]
+    fn digests_for_several_algorithms() {
+        let mut tmp = NamedTempFile::new().unwrap();
+        write!(tmp, "hello world").unwrap();
+        let algs = [HashAlgorithm::Sha512, HashAlgorithm::Blake3];
+        let sums = calculate_digests(tmp.path().to_str().unwrap(), &algs).unwrap();
+        assert_eq!(sums.len(), 2);
+        assert!(sums[&HashAlgorithm::Sha512].starts_with("309ecc489c12d6eb4cc40f50c902f2b4"));
+        assert_eq!(
+            sums[&HashAlgorithm::Blake3],
+            "d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24"
+        );
+    }
+
+    #[test]
+    fn streams_files_larger_than_the_buffer() {
+        let mut tmp = NamedTempFile::new().unwrap();
+        let data: Vec<u8> = (0..BUF_SIZE * 3 + 17).map(|i| (i % 251) as u8).collect();
+        tmp.write_all(&data).unwrap();
+        let expected = {
+            use sha2::{Digest, Sha256};
+            format!("{:x}", Sha256::digest(&data))
+        };
+        assert_eq!(calculate_checksum(tmp.path()).unwrap(), expected);
+    }
+
+    #[test]
+    fn head_tail_ignores_the_middle() {
+        let mut a = NamedTempFile::new().unwrap();
+        let mut b = NamedTempFile::new().unwrap();
+        write!(a, "HEAD-aaaaaaaaaa-TAIL").unwrap();
+        write!(b, "HEAD-bbbbbbbbbb-TAIL").unwrap();
+        let algs = [HashAlgorithm::Sha256];
+        let scope = HashScope::HeadTail(4);
+        assert_eq!(
+            hash_file(a.path(), &algs, scope).unwrap(),
+            hash_file(b.path(), &algs, scope).unwrap()
+        );
+        assert_ne!(
+            hash_file(a.path(), &algs, HashScope::Full).unwrap(),
+            hash_file(b.path(), &algs, HashScope::Full).unwrap()
+        );
+        assert!(hash_file(a.path(), &algs, HashScope::Skipped).unwrap().is_empty());
+    }
 }
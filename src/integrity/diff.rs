@@ -0,0 +1,305 @@
+// Comparison of two baseline files, e.g. a golden image against a host (`watchdogfs diff`)
+use crate::config::Attribute;
+use crate::integrity::signing::Keys;
+use crate::integrity::{
+    AttributeChange, Baseline, BaselineFile, IgnoreMatcher, read_baseline_unverified,
+};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+/// Exit code when both baselines agree.
+pub const EXIT_SAME: i32 = 0;
+/// Exit code when the baselines differ.
+pub const EXIT_DIFFERENT: i32 = 1;
+/// Exit code when a baseline could not be read.
+pub const EXIT_ERROR: i32 = 2;
+
+/// How an entry differs from the first baseline to the second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffKind {
+    /// Only in the second baseline
+    Added,
+    /// Only in the first baseline
+    Removed,
+    /// In both, with different content
+    Modified,
+    /// In both, same content, other compared attributes differ
+    Metadata,
+}
+
+impl DiffKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiffKind::Added => "added",
+            DiffKind::Removed => "removed",
+            DiffKind::Modified => "modified",
+            DiffKind::Metadata => "metadata",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffEntry {
+    pub kind: DiffKind,
+    pub path: String,
+    /// Compared attributes that differ (modified and metadata entries only)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<AttributeChange>,
+}
+
+/// Limits a comparison to some paths.
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    /// Only paths equal to or below one of these (all paths if empty)
+    pub include: Vec<PathBuf>,
+    /// gitignore-style patterns of paths to leave out
+    pub exclude: IgnoreMatcher,
+}
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        Ok(PathFilter {
+            include: include.iter().map(PathBuf::from).collect(),
+            exclude: IgnoreMatcher::new(exclude)?,
+        })
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        let path = Path::new(path);
+        (self.include.is_empty() || self.include.iter().any(|p| path.starts_with(p)))
+            && !self.exclude.is_ignored(path, false)
+    }
+}
+
+/// The result of comparing baseline `from` with baseline `to`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BaselineDiff {
+    pub from: String,
+    pub to: String,
+    /// Sorted by path
+    pub entries: Vec<DiffEntry>,
+}
+
+/// Classify every path of `old` and `new` accepted by `filter`. Entries present in
+/// both are compared on `attrs`; content counts as modified only if both sides
+/// share a hash algorithm.
+pub fn diff(
+    old: &Baseline,
+    new: &Baseline,
+    attrs: &[Attribute],
+    filter: &PathFilter,
+) -> Vec<DiffEntry> {
+    let entry = |kind, path: &String, attributes| DiffEntry {
+        kind,
+        path: path.clone(),
+        attributes,
+    };
+    let mut entries = Vec::new();
+    for (path, old_entry) in old.iter().filter(|(p, _)| filter.matches(p)) {
+        match new.get(path) {
+            None => entries.push(entry(DiffKind::Removed, path, Vec::new())),
+            Some(new_entry) => {
+                let changed = old_entry.diff(new_entry, attrs);
+                if changed.is_empty() {
+                    continue;
+                }
+                let kind = if changed.iter().any(|c| c.attribute == Attribute::Content) {
+                    DiffKind::Modified
+                } else {
+                    DiffKind::Metadata
+                };
+                entries.push(entry(kind, path, changed));
+            }
+        }
+    }
+    for path in new
+        .keys()
+        .filter(|p| filter.matches(p) && !old.contains_key(*p))
+    {
+        entries.push(entry(DiffKind::Added, path, Vec::new()));
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries
+}
+
+/// Read the baseline files `from` and `to` (their signatures verified with `keys` if
+/// given; they may belong to different jobs or hosts) and compare them.
+pub fn run(
+    from: &str,
+    to: &str,
+    attrs: &[Attribute],
+    filter: &PathFilter,
+    keys: Option<&Keys>,
+) -> Result<BaselineDiff> {
+    let load = |path: &str| -> Result<BaselineFile> {
+        let file = read_baseline_unverified(Path::new(path))
+            .with_context(|| format!("loading baseline {}", path))?;
+        if let Some(keys) = keys {
+            file.verify_signature(keys)
+                .with_context(|| format!("verifying signature of {}", path))?;
+        }
+        Ok(file)
+    };
+    let (old, new) = (load(from)?, load(to)?);
+    Ok(BaselineDiff {
+        from: from.to_string(),
+        to: to.to_string(),
+        entries: diff(&old.entries, &new.entries, attrs, filter),
+    })
+}
+
+impl BaselineDiff {
+    pub fn exit_code(&self) -> i32 {
+        if self.entries.is_empty() {
+            EXIT_SAME
+        } else {
+            EXIT_DIFFERENT
+        }
+    }
+
+    fn count(&self, kind: DiffKind) -> usize {
+        self.entries.iter().filter(|e| e.kind == kind).count()
+    }
+
+    fn counts(&self) -> String {
+        [
+            DiffKind::Added,
+            DiffKind::Removed,
+            DiffKind::Modified,
+            DiffKind::Metadata,
+        ]
+        .iter()
+        .map(|k| format!("{} {}", self.count(*k), k.as_str()))
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+
+    /// One line per entry, attribute changes indented below it.
+    pub fn to_text(&self) -> String {
+        let mut out = format!("--- {}\n+++ {}\n", self.from, self.to);
+        for entry in &self.entries {
+            let _ = writeln!(out, "{:<9} {}", entry.kind.as_str(), entry.path);
+            for attr in &entry.attributes {
+                let _ = writeln!(
+                    out,
+                    "          {}: {} -> {}",
+                    attr.attribute.as_str(),
+                    attr.old,
+                    attr.new
+                );
+            }
+        }
+        let _ = writeln!(out, "{}", self.counts());
+        out
+    }
+
+    /// A summary line and a table, for pasting into tickets and pull requests.
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!(
+            "### Baseline diff: `{}` → `{}`\n\n{}\n",
+            self.from,
+            self.to,
+            self.counts()
+        );
+        if self.entries.is_empty() {
+            return out;
+        }
+        out.push_str("\n| Change | Path | Details |\n|---|---|---|\n");
+        for entry in &self.entries {
+            let details: Vec<String> = entry
+                .attributes
+                .iter()
+                .map(|a| format!("{}: `{}` → `{}`", a.attribute.as_str(), a.old, a.new))
+                .collect();
+            let _ = writeln!(
+                out,
+                "| {} | `{}` | {} |",
+                entry.kind.as_str(),
+                entry.path,
+                details.join("<br>").replace('|', "\\|")
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BaselineDiff, DiffKind, PathFilter, diff};
+    use crate::config::{Attribute, default_attributes};
+    use crate::integrity::{Baseline, Entry};
+
+    fn entry(hash: &str, mode: u32) -> Entry {
+        let mut entry = Entry::from(hash);
+        entry.mode = Some(mode);
+        entry
+    }
+
+    fn baselines() -> (Baseline, Baseline) {
+        let mut golden = Baseline::new();
+        golden.insert("/etc/motd".into(), entry("aa", 0o644));
+        golden.insert("/etc/passwd".into(), entry("bb", 0o644));
+        golden.insert("/usr/bin/sudo".into(), entry("cc", 0o4755));
+        let mut host = Baseline::new();
+        host.insert("/etc/passwd".into(), entry("bx", 0o644));
+        host.insert("/usr/bin/sudo".into(), entry("cc", 0o755));
+        host.insert("/usr/bin/nc".into(), entry("dd", 0o755));
+        (golden, host)
+    }
+
+    #[test]
+    fn classifies_entries() {
+        let (golden, host) = baselines();
+        let entries = diff(
+            &golden,
+            &host,
+            &default_attributes(),
+            &PathFilter::default(),
+        );
+        let kinds: Vec<_> = entries.iter().map(|e| (e.kind, e.path.as_str())).collect();
+        assert_eq!(
+            kinds,
+            [
+                (DiffKind::Removed, "/etc/motd"),
+                (DiffKind::Modified, "/etc/passwd"),
+                (DiffKind::Added, "/usr/bin/nc"),
+                (DiffKind::Metadata, "/usr/bin/sudo"),
+            ]
+        );
+        assert_eq!(entries[3].attributes[0].attribute, Attribute::Mode);
+    }
+
+    #[test]
+    fn filters_limit_the_comparison() {
+        let (golden, host) = baselines();
+        let filter = PathFilter::new(&["/usr".into()], &["nc".into()]).unwrap();
+        let entries = diff(&golden, &host, &default_attributes(), &filter);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, "/usr/bin/sudo");
+
+        let only_content = diff(&golden, &host, &[Attribute::Content], &filter);
+        assert!(only_content.is_empty());
+    }
+
+    #[test]
+    fn markdown_has_a_row_per_entry() {
+        let (golden, host) = baselines();
+        let report = BaselineDiff {
+            from: "golden.json".into(),
+            to: "host.json".into(),
+            entries: diff(
+                &golden,
+                &host,
+                &default_attributes(),
+                &PathFilter::default(),
+            ),
+        };
+        let md = report.to_markdown();
+        assert!(md.contains("1 added, 1 removed, 1 modified, 1 metadata"));
+        assert!(md.contains("| metadata | `/usr/bin/sudo` | mode: `4755` → `0755` |"));
+        assert_eq!(report.exit_code(), super::EXIT_DIFFERENT);
+    }
+}
@@ -0,0 +1,614 @@
+// Per-file baseline entries: content hash plus inode metadata
+use crate::config::{
+    Attribute, HashAlgorithm, JobConfig, LargeFilePolicy, default_hash_algorithms,
+};
+use crate::integrity::extattr::{
+    self, XATTR_ACL_ACCESS, XATTR_ACL_DEFAULT, XATTR_CAPABILITY, XATTR_SELINUX,
+};
+use crate::integrity::{Digests, HEAD_TAIL_BYTES, HashScope, hash_file};
+use crate::utils::from_hex;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::Path;
+
+/// The kind of inode a baseline entry describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    Fifo,
+    Socket,
+    Block,
+    Char,
+}
+
+impl FileKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileKind::File => "file",
+            FileKind::Dir => "dir",
+            FileKind::Symlink => "symlink",
+            FileKind::Fifo => "fifo",
+            FileKind::Socket => "socket",
+            FileKind::Block => "block",
+            FileKind::Char => "char",
+        }
+    }
+
+    fn of(ft: fs::FileType) -> Self {
+        if ft.is_symlink() {
+            FileKind::Symlink
+        } else if ft.is_dir() {
+            FileKind::Dir
+        } else if ft.is_fifo() {
+            FileKind::Fifo
+        } else if ft.is_socket() {
+            FileKind::Socket
+        } else if ft.is_block_device() {
+            FileKind::Block
+        } else if ft.is_char_device() {
+            FileKind::Char
+        } else {
+            FileKind::File
+        }
+    }
+}
+
+/// Everything a baseline records about one file.
+///
+/// Metadata fields are optional because baselines written before they existed
+/// only carry the hash; a missing value is never reported as a change. Likewise
+/// content is only compared using the algorithms both sides have a digest for.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "EntryRepr")]
+pub struct Entry {
+    /// Hex content digests, by algorithm
+    pub hashes: Digests,
+    /// What part of the content `hashes` cover (omitted when the whole file)
+    #[serde(default, skip_serializing_if = "HashScope::is_full")]
+    pub hashed: HashScope,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_type: Option<FileKind>,
+    /// Permission bits, including setuid/setgid/sticky
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inode: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nlink: Option<u64>,
+    /// Modification time, nanoseconds since the epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<i64>,
+    /// Status change time, nanoseconds since the epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ctime: Option<i64>,
+    /// Extended attributes, name to hex-encoded value (only when the job tracks them)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xattrs: Option<BTreeMap<String, String>>,
+    /// Inode flags from `FS_IOC_GETFLAGS` (only when the job tracks them)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flags: Option<u32>,
+}
+
+/// On-disk shape of an entry: older baselines store just the SHA-256 string,
+/// or a `hash` field holding it.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EntryRepr {
+    Hash(String),
+    Full(Box<FullEntry>),
+}
+
+#[derive(Deserialize)]
+struct FullEntry {
+    #[serde(default)]
+    hash: Option<String>,
+    #[serde(default)]
+    hashes: Digests,
+    #[serde(default)]
+    hashed: HashScope,
+    file_type: Option<FileKind>,
+    mode: Option<u32>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    size: Option<u64>,
+    inode: Option<u64>,
+    nlink: Option<u64>,
+    mtime: Option<i64>,
+    ctime: Option<i64>,
+    xattrs: Option<BTreeMap<String, String>>,
+    flags: Option<u32>,
+}
+
+impl From<EntryRepr> for Entry {
+    fn from(repr: EntryRepr) -> Self {
+        match repr {
+            EntryRepr::Hash(hash) => Entry::from(hash.as_str()),
+            EntryRepr::Full(e) => Entry {
+                hashes: match e.hash {
+                    Some(legacy) if e.hashes.is_empty() => {
+                        Digests::from([(HashAlgorithm::Sha256, legacy)])
+                    }
+                    _ => e.hashes,
+                },
+                hashed: e.hashed,
+                file_type: e.file_type,
+                mode: e.mode,
+                uid: e.uid,
+                gid: e.gid,
+                size: e.size,
+                inode: e.inode,
+                nlink: e.nlink,
+                mtime: e.mtime,
+                ctime: e.ctime,
+                xattrs: e.xattrs,
+                flags: e.flags,
+            },
+        }
+    }
+}
+
+/// One attribute that differs between a baseline entry and the disk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AttributeChange {
+    pub attribute: Attribute,
+    pub old: String,
+    pub new: String,
+}
+
+/// What `Entry::from_path` computes besides the `lstat` metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryOptions {
+    /// Content digests to record
+    pub hash_algorithms: Vec<HashAlgorithm>,
+    /// Size above which `large_files` applies
+    pub max_file_size: Option<u64>,
+    pub large_files: LargeFilePolicy,
+    /// Hash this much of every file whatever its size, instead of `max_file_size`
+    /// and `large_files` (set by `covering`)
+    pub scope: Option<HashScope>,
+    /// Read every extended attribute (backs xattrs, ACLs, SELinux labels and capabilities)
+    pub xattrs: bool,
+    /// Read inode flags
+    pub flags: bool,
+}
+
+impl Default for EntryOptions {
+    fn default() -> Self {
+        EntryOptions {
+            hash_algorithms: default_hash_algorithms(),
+            max_file_size: None,
+            large_files: LargeFilePolicy::default(),
+            scope: None,
+            xattrs: false,
+            flags: false,
+        }
+    }
+}
+
+impl EntryOptions {
+    /// Collect only what the job's `attributes` compare, hashed with its `hash_algorithms`.
+    pub fn for_job(job_cfg: &JobConfig) -> Self {
+        let wants = |a: &[Attribute]| job_cfg.attributes.iter().any(|x| a.contains(x));
+        EntryOptions {
+            hash_algorithms: job_cfg.hash_algorithms.clone(),
+            max_file_size: job_cfg.max_file_size,
+            large_files: job_cfg.large_files,
+            scope: None,
+            xattrs: wants(&[
+                Attribute::Xattrs,
+                Attribute::Acl,
+                Attribute::Selinux,
+                Attribute::Capabilities,
+            ]),
+            flags: wants(&[Attribute::Flags]),
+        }
+    }
+
+    /// These options, additionally computing every digest `baseline` holds, so a file
+    /// can still be compared after the job switched algorithms. The file is hashed over
+    /// the same part as `baseline` was, so one that grew past (or shrank below)
+    /// `max_file_size` is still compared.
+    pub fn covering(&self, baseline: &Entry) -> EntryOptions {
+        let mut opts = self.clone();
+        for alg in baseline.hashes.keys() {
+            if !opts.hash_algorithms.contains(alg) {
+                opts.hash_algorithms.push(*alg);
+            }
+        }
+        // A skipped entry has nothing to compare against
+        if baseline.hashed != HashScope::Skipped {
+            opts.scope = Some(baseline.hashed);
+        }
+        opts
+    }
+
+    /// How much of a file of `size` bytes to hash.
+    pub fn scope_for(&self, size: u64) -> HashScope {
+        if let Some(scope) = self.scope {
+            return scope;
+        }
+        match self.max_file_size {
+            Some(max) if size > max => match self.large_files {
+                LargeFilePolicy::Skip => HashScope::Skipped,
+                LargeFilePolicy::HeadTail => HashScope::HeadTail(HEAD_TAIL_BYTES),
+                LargeFilePolicy::Full => HashScope::Full,
+            },
+            _ => HashScope::Full,
+        }
+    }
+}
+
+impl Entry {
+    /// Hash `path` and record its metadata (from `lstat`, so symlinks describe themselves).
+    pub fn from_path(path: &str, opts: &EntryOptions) -> Result<Self> {
+        let meta = fs::symlink_metadata(path).with_context(|| format!("stat {}", path))?;
+        // Symlinks are hashed through to their target, so size the target
+        let size = fs::metadata(path).map_or(meta.size(), |m| m.len());
+        let hashed = opts.scope_for(size);
+        let hashes = hash_file(Path::new(path), &opts.hash_algorithms, hashed)?;
+        Ok(Entry {
+            hashes,
+            hashed,
+            file_type: Some(FileKind::of(meta.file_type())),
+            mode: Some(meta.mode() & 0o7777),
+            uid: Some(meta.uid()),
+            gid: Some(meta.gid()),
+            size: Some(meta.size()),
+            inode: Some(meta.ino()),
+            nlink: Some(meta.nlink()),
+            mtime: Some(meta.mtime() * 1_000_000_000 + meta.mtime_nsec()),
+            ctime: Some(meta.ctime() * 1_000_000_000 + meta.ctime_nsec()),
+            xattrs: if opts.xattrs {
+                Some(
+                    extattr::read_xattrs(Path::new(path))
+                        .with_context(|| format!("reading xattrs of {}", path))?,
+                )
+            } else {
+                None
+            },
+            flags: if opts.flags {
+                Some(
+                    extattr::read_inode_flags(Path::new(path))
+                        .with_context(|| format!("reading inode flags of {}", path))?,
+                )
+            } else {
+                None
+            },
+        })
+    }
+
+    /// The digest of the first algorithm recorded, for display.
+    pub fn digest(&self) -> Option<&str> {
+        self.hashes.values().next().map(String::as_str)
+    }
+
+    /// Digests of both entries restricted to the algorithms they share,
+    /// rendered as `alg:hex` lists; `None` if they share none or cover
+    /// different parts of the file.
+    fn common_digests(&self, other: &Entry) -> Option<(String, String)> {
+        if self.hashed != other.hashed {
+            return None;
+        }
+        let common: Vec<_> = self
+            .hashes
+            .iter()
+            .filter_map(|(alg, old)| Some((alg, old, other.hashes.get(alg)?)))
+            .collect();
+        if common.is_empty() {
+            return None;
+        }
+        let render = |pick: fn(&(&HashAlgorithm, &String, &String)) -> String| {
+            common.iter().map(pick).collect::<Vec<_>>().join(" ")
+        };
+        Some((
+            render(|(alg, old, _)| format!("{}:{}", alg.as_str(), old)),
+            render(|(alg, _, new)| format!("{}:{}", alg.as_str(), new)),
+        ))
+    }
+
+    /// True if both entries have a digest for some common algorithm and all
+    /// shared digests agree.
+    pub fn same_content(&self, other: &Entry) -> bool {
+        self.common_digests(other).is_some_and(|(a, b)| a == b)
+    }
+
+    /// Raw value of one recorded extended attribute; `Some(None)` if xattrs
+    /// were read but `name` is not set.
+    fn xattr(&self, name: &str) -> Option<Option<Vec<u8>>> {
+        let xattrs = self.xattrs.as_ref()?;
+        Some(xattrs.get(name).and_then(|v| from_hex(v)))
+    }
+
+    /// Human-readable value of `attr`, if this entry records it.
+    pub fn attribute(&self, attr: Attribute) -> Option<String> {
+        match attr {
+            Attribute::Content => Some(format_digests(&self.hashes)),
+            Attribute::Type => self.file_type.map(|t| t.as_str().to_string()),
+            Attribute::Mode => self.mode.map(|m| format!("{:04o}", m)),
+            Attribute::Uid => self.uid.map(|v| v.to_string()),
+            Attribute::Gid => self.gid.map(|v| v.to_string()),
+            Attribute::Size => self.size.map(|v| v.to_string()),
+            Attribute::Inode => self.inode.map(|v| v.to_string()),
+            Attribute::Nlink => self.nlink.map(|v| v.to_string()),
+            Attribute::Mtime => self.mtime.map(format_nanos),
+            Attribute::Ctime => self.ctime.map(format_nanos),
+            Attribute::Xattrs => self.xattrs.as_ref().map(|x| {
+                x.iter()
+                    .map(|(k, v)| format!("{}=0x{}", k, v))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            }),
+            Attribute::Acl => {
+                let render = |v: Option<Vec<u8>>| {
+                    v.map(|v| extattr::decode_acl(&v).unwrap_or_else(|| "<invalid>".to_string()))
+                };
+                let access = render(self.xattr(XATTR_ACL_ACCESS)?);
+                let default = render(self.xattr(XATTR_ACL_DEFAULT)?).map(|d| {
+                    d.split(',')
+                        .map(|e| format!("default:{}", e))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                });
+                Some(
+                    [access, default]
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<_>>()
+                        .join(","),
+                )
+            }
+            Attribute::Selinux => self.xattr(XATTR_SELINUX).map(|v| {
+                v.map(|v| {
+                    String::from_utf8_lossy(&v)
+                        .trim_end_matches('\0')
+                        .to_string()
+                })
+                .unwrap_or_default()
+            }),
+            Attribute::Capabilities => self.xattr(XATTR_CAPABILITY).map(|v| {
+                v.map(|v| {
+                    extattr::decode_capabilities(&v).unwrap_or_else(|| "<invalid>".to_string())
+                })
+                .unwrap_or_default()
+            }),
+            Attribute::Flags => self.flags.map(extattr::format_flags),
+        }
+    }
+
+    /// The attributes in `attrs` whose values differ between `self` (the baseline)
+    /// and `current`. Attributes either side does not record are skipped.
+    pub fn diff(&self, current: &Entry, attrs: &[Attribute]) -> Vec<AttributeChange> {
+        attrs
+            .iter()
+            .filter_map(|&attribute| {
+                let (old, new) = match attribute {
+                    Attribute::Content => self.common_digests(current)?,
+                    _ => (self.attribute(attribute)?, current.attribute(attribute)?),
+                };
+                (old != new).then_some(AttributeChange {
+                    attribute,
+                    old,
+                    new,
+                })
+            })
+            .collect()
+    }
+}
+
+fn format_digests(hashes: &Digests) -> String {
+    hashes
+        .iter()
+        .map(|(alg, hex)| format!("{}:{}", alg.as_str(), hex))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn format_nanos(ns: i64) -> String {
+    format!(
+        "{}.{:09}",
+        ns.div_euclid(1_000_000_000),
+        ns.rem_euclid(1_000_000_000)
+    )
+}
+
+/// An entry that only knows its SHA-256, as in baselines written before metadata was tracked.
+impl From<&str> for Entry {
+    fn from(hash: &str) -> Self {
+        Entry {
+            hashes: Digests::from([(HashAlgorithm::Sha256, hash.to_string())]),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entry, EntryOptions, FileKind};
+    use crate::config::{Attribute, HashAlgorithm, LargeFilePolicy};
+    use crate::integrity::{HEAD_TAIL_BYTES, HashScope};
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+
+    #[test]
+    fn legacy_hash_string_deserializes() {
+        let e: Entry = serde_json::from_str("\"abc\"").unwrap();
+        assert_eq!(e.hashes[&HashAlgorithm::Sha256], "abc");
+        assert!(e.mode.is_none());
+    }
+
+    #[test]
+    fn from_path_records_metadata() {
+        let dir = tempdir().unwrap();
+        let f = dir.path().join("passwd");
+        fs::write(&f, "root:x:0:0::/root:/bin/sh\n").unwrap();
+        fs::set_permissions(&f, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let e = Entry::from_path(f.to_str().unwrap(), &EntryOptions::default()).unwrap();
+        assert_eq!(e.file_type, Some(FileKind::File));
+        assert_eq!(e.mode, Some(0o644));
+        assert_eq!(e.size, Some(26));
+
+        let json = serde_json::to_string(&e).unwrap();
+        let back: Entry = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, e);
+    }
+
+    #[test]
+    fn diff_reports_selected_attributes_only() {
+        let dir = tempdir().unwrap();
+        let f = dir.path().join("sudo");
+        fs::write(&f, "binary").unwrap();
+        fs::set_permissions(&f, fs::Permissions::from_mode(0o755)).unwrap();
+        let before = Entry::from_path(f.to_str().unwrap(), &EntryOptions::default()).unwrap();
+        fs::set_permissions(&f, fs::Permissions::from_mode(0o4755)).unwrap();
+        let after = Entry::from_path(f.to_str().unwrap(), &EntryOptions::default()).unwrap();
+
+        let changes = before.diff(&after, &[Attribute::Content, Attribute::Mode]);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].attribute, Attribute::Mode);
+        assert_eq!(changes[0].old, "0755");
+        assert_eq!(changes[0].new, "4755");
+
+        assert!(before.diff(&after, &[Attribute::Content]).is_empty());
+    }
+
+    #[test]
+    fn unknown_legacy_values_are_not_changes() {
+        let legacy = Entry::from("abc");
+        let mut current = Entry::from("abc");
+        current.mode = Some(0o600);
+        assert!(legacy.diff(&current, &[Attribute::Mode]).is_empty());
+    }
+
+    #[test]
+    fn xattr_attributes_are_decoded() {
+        let mut e = Entry::from("abc");
+        assert!(e.attribute(Attribute::Selinux).is_none());
+
+        let mut xattrs = std::collections::BTreeMap::new();
+        xattrs.insert(
+            "security.selinux".to_string(),
+            crate::utils::to_hex(b"system_u:object_r:shadow_t:s0\0"),
+        );
+        e.xattrs = Some(xattrs);
+        assert_eq!(
+            e.attribute(Attribute::Selinux).unwrap(),
+            "system_u:object_r:shadow_t:s0"
+        );
+        // Tracked but unset
+        assert_eq!(e.attribute(Attribute::Capabilities).unwrap(), "");
+        assert_eq!(e.attribute(Attribute::Acl).unwrap(), "");
+    }
+
+    #[test]
+    fn options_follow_job_attributes() {
+        let mut cfg = crate::config::JobConfig::default();
+        assert_eq!(EntryOptions::for_job(&cfg), EntryOptions::default());
+        cfg.attributes.push(Attribute::Acl);
+        cfg.attributes.push(Attribute::Flags);
+        let opts = EntryOptions::for_job(&cfg);
+        assert!(opts.xattrs && opts.flags);
+    }
+
+    #[test]
+    fn content_compares_only_shared_algorithms() {
+        let dir = tempdir().unwrap();
+        let f = dir.path().join("big.iso");
+        fs::write(&f, "data").unwrap();
+        let path = f.to_str().unwrap();
+        let legacy = Entry::from_path(path, &EntryOptions::default()).unwrap();
+
+        let blake = EntryOptions {
+            hash_algorithms: vec![HashAlgorithm::Blake3],
+            ..Default::default()
+        };
+        let current = Entry::from_path(path, &blake).unwrap();
+        // Nothing in common: cannot compare, so no (false) alert
+        assert!(legacy.diff(&current, &[Attribute::Content]).is_empty());
+        assert!(!legacy.same_content(&current));
+
+        let covering = Entry::from_path(path, &blake.covering(&legacy)).unwrap();
+        assert_eq!(covering.hashes.len(), 2);
+        assert!(legacy.same_content(&covering));
+
+        fs::write(&f, "tampered").unwrap();
+        let after = Entry::from_path(path, &blake.covering(&legacy)).unwrap();
+        let changes = legacy.diff(&after, &[Attribute::Content]);
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].old.starts_with("sha256:"));
+    }
+
+    #[test]
+    fn legacy_hash_field_is_sha256() {
+        let e: Entry = serde_json::from_str(r#"{"hash":"abc","mode":420}"#).unwrap();
+        assert_eq!(e, {
+            let mut x = Entry::from("abc");
+            x.mode = Some(0o644);
+            x
+        });
+        let json = serde_json::to_string(&e).unwrap();
+        assert!(json.contains(r#""hashes":{"sha256":"abc"}"#));
+    }
+
+    #[test]
+    fn large_files_follow_policy() {
+        let dir = tempdir().unwrap();
+        let f = dir.path().join("disk.img");
+        fs::write(&f, vec![0u8; 4096]).unwrap();
+        let path = f.to_str().unwrap();
+        let mut opts = EntryOptions {
+            max_file_size: Some(1024),
+            large_files: LargeFilePolicy::Skip,
+            ..Default::default()
+        };
+        let skipped = Entry::from_path(path, &opts).unwrap();
+        assert_eq!(skipped.hashed, HashScope::Skipped);
+        assert!(skipped.hashes.is_empty());
+        assert_eq!(skipped.size, Some(4096));
+
+        opts.large_files = LargeFilePolicy::HeadTail;
+        let partial = Entry::from_path(path, &opts).unwrap();
+        assert_eq!(partial.hashed, HashScope::HeadTail(HEAD_TAIL_BYTES));
+        // Digests over different parts of the file are never compared
+        let full = Entry::from_path(path, &EntryOptions::default()).unwrap();
+        assert!(full.diff(&partial, &[Attribute::Content]).is_empty());
+
+        let json = serde_json::to_string(&partial).unwrap();
+        assert!(json.contains(r#""hashed":{"head-tail":1048576}"#));
+        assert_eq!(serde_json::from_str::<Entry>(&json).unwrap(), partial);
+    }
+
+    #[test]
+    fn growing_past_max_file_size_is_still_compared() {
+        let dir = tempdir().unwrap();
+        let f = dir.path().join("app.log");
+        fs::write(&f, "started").unwrap();
+        let path = f.to_str().unwrap();
+        let opts = EntryOptions {
+            max_file_size: Some(1024),
+            large_files: LargeFilePolicy::HeadTail,
+            ..Default::default()
+        };
+        let baseline = Entry::from_path(path, &opts).unwrap();
+        assert_eq!(baseline.hashed, HashScope::Full);
+
+        fs::write(&f, vec![b'x'; 4096]).unwrap();
+        let current = Entry::from_path(path, &opts.covering(&baseline)).unwrap();
+        assert_eq!(current.hashed, HashScope::Full);
+        let changed = baseline.diff(&current, &[Attribute::Content]);
+        assert_eq!(changed.len(), 1);
+    }
+}
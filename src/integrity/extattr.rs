@@ -0,0 +1,330 @@
+// Extended attributes, POSIX ACLs, file capabilities and inode flags (Linux)
+use crate::utils::to_hex;
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fmt::Write as _;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+pub const XATTR_SELINUX: &str = "security.selinux";
+pub const XATTR_CAPABILITY: &str = "security.capability";
+pub const XATTR_ACL_ACCESS: &str = "system.posix_acl_access";
+pub const XATTR_ACL_DEFAULT: &str = "system.posix_acl_default";
+
+fn c_path(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// Call a "size query, then fill" style syscall until the buffer is large enough.
+fn read_sized(mut call: impl FnMut(*mut u8, usize) -> isize) -> io::Result<Vec<u8>> {
+    loop {
+        let len = call(std::ptr::null_mut(), 0);
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = vec![0u8; len as usize];
+        let got = call(buf.as_mut_ptr(), buf.len());
+        if got >= 0 {
+            buf.truncate(got as usize);
+            return Ok(buf);
+        }
+        let err = io::Error::last_os_error();
+        // The value grew between the two calls: ask again
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+    }
+}
+
+/// All extended attributes of `path` (symlinks are not followed), values hex-encoded.
+/// A filesystem without xattr support yields an empty map.
+pub fn read_xattrs(path: &Path) -> io::Result<BTreeMap<String, String>> {
+    let cpath = c_path(path)?;
+    let names = match read_sized(|buf, len| unsafe {
+        // SAFETY: `cpath` is NUL-terminated and `buf` is valid for `len` bytes (or null with 0).
+        libc::llistxattr(cpath.as_ptr(), buf.cast(), len)
+    }) {
+        Ok(names) => names,
+        Err(e) if e.raw_os_error() == Some(libc::ENOTSUP) => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut xattrs = BTreeMap::new();
+    for name in names.split(|b| *b == 0).filter(|n| !n.is_empty()) {
+        let cname =
+            CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let value = match read_sized(|buf, len| unsafe {
+            // SAFETY: both strings are NUL-terminated and `buf` is valid for `len` bytes.
+            libc::lgetxattr(cpath.as_ptr(), cname.as_ptr(), buf.cast(), len)
+        }) {
+            Ok(value) => value,
+            // Removed between listing and reading
+            Err(e) if e.raw_os_error() == Some(libc::ENODATA) => continue,
+            Err(e) => return Err(e),
+        };
+        xattrs.insert(String::from_utf8_lossy(name).into_owned(), to_hex(&value));
+    }
+    Ok(xattrs)
+}
+
+/// Inode flags of `path` as reported by `lsattr` (`FS_IOC_GETFLAGS`).
+/// Only regular files and directories are opened; anything else reports no flags.
+#[cfg(target_os = "linux")]
+pub fn read_inode_flags(path: &Path) -> io::Result<u32> {
+    use std::fs::OpenOptions;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+
+    let meta = std::fs::symlink_metadata(path)?;
+    if !meta.is_file() && !meta.is_dir() {
+        return Ok(0);
+    }
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK | libc::O_NOATIME)
+        .open(path)
+        .or_else(|_| {
+            // O_NOATIME is only permitted for the file owner
+            OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+                .open(path)
+        })?;
+    let mut flags: libc::c_long = 0;
+    // SAFETY: the fd is open for the lifetime of `file` and `flags` is a valid out-pointer.
+    let rc = unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) };
+    if rc < 0 {
+        let err = io::Error::last_os_error();
+        // Filesystems without inode flags (tmpfs on older kernels, FUSE, …)
+        if matches!(err.raw_os_error(), Some(libc::ENOTTY) | Some(libc::ENOTSUP)) {
+            return Ok(0);
+        }
+        return Err(err);
+    }
+    Ok(flags as u32)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_inode_flags(_path: &Path) -> io::Result<u32> {
+    Ok(0)
+}
+
+/// `lsattr` letters for the set flags (e.g. `ia` for immutable + append-only), `-` if none.
+pub fn format_flags(flags: u32) -> String {
+    const LETTERS: &[(u32, char)] = &[
+        (0x0000_0001, 's'),
+        (0x0000_0002, 'u'),
+        (0x0000_0008, 'S'),
+        (0x0001_0000, 'D'),
+        (0x0000_0010, 'i'),
+        (0x0000_0020, 'a'),
+        (0x0000_0040, 'd'),
+        (0x0000_0080, 'A'),
+        (0x0000_0004, 'c'),
+        (0x0000_0800, 'E'),
+        (0x0000_4000, 'j'),
+        (0x0000_1000, 'I'),
+        (0x0000_8000, 't'),
+        (0x0002_0000, 'T'),
+        (0x0008_0000, 'e'),
+        (0x0080_0000, 'C'),
+        (0x0200_0000, 'x'),
+        (0x4000_0000, 'F'),
+        (0x1000_0000, 'N'),
+        (0x2000_0000, 'P'),
+        (0x0010_0000, 'V'),
+        (0x0000_0400, 'm'),
+    ];
+    let mut out: String = LETTERS
+        .iter()
+        .filter(|(bit, _)| flags & bit != 0)
+        .map(|(_, c)| *c)
+        .collect();
+    let unknown = flags & !LETTERS.iter().fold(0, |acc, (bit, _)| acc | bit);
+    if unknown != 0 {
+        let _ = write!(out, "+{:#x}", unknown);
+    }
+    if out.is_empty() {
+        out.push('-');
+    }
+    out
+}
+
+/// Decode a `system.posix_acl_*` value into `getfacl`-style short text,
+/// e.g. `user::rw-,user:1000:r--,group::r--,mask::r--,other::r--`.
+pub fn decode_acl(value: &[u8]) -> Option<String> {
+    const VERSION: u32 = 2;
+    if value.len() < 4 || u32::from_le_bytes(value[..4].try_into().ok()?) != VERSION {
+        return None;
+    }
+    let mut parts = Vec::new();
+    for chunk in value[4..].chunks_exact(8) {
+        let tag = u16::from_le_bytes([chunk[0], chunk[1]]);
+        let perm = u16::from_le_bytes([chunk[2], chunk[3]]);
+        let id = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        let (kind, qualifier) = match tag {
+            0x01 => ("user", String::new()),
+            0x02 => ("user", id.to_string()),
+            0x04 => ("group", String::new()),
+            0x08 => ("group", id.to_string()),
+            0x10 => ("mask", String::new()),
+            0x20 => ("other", String::new()),
+            _ => return None,
+        };
+        let rwx: String = [(4, 'r'), (2, 'w'), (1, 'x')]
+            .iter()
+            .map(|(bit, c)| if perm & bit != 0 { *c } else { '-' })
+            .collect();
+        parts.push(format!("{}:{}:{}", kind, qualifier, rwx));
+    }
+    Some(parts.join(","))
+}
+
+const CAPABILITIES: &[&str] = &[
+    "chown",
+    "dac_override",
+    "dac_read_search",
+    "fowner",
+    "fsetid",
+    "kill",
+    "setgid",
+    "setuid",
+    "setpcap",
+    "linux_immutable",
+    "net_bind_service",
+    "net_broadcast",
+    "net_admin",
+    "net_raw",
+    "ipc_lock",
+    "ipc_owner",
+    "sys_module",
+    "sys_rawio",
+    "sys_chroot",
+    "sys_ptrace",
+    "sys_pacct",
+    "sys_admin",
+    "sys_boot",
+    "sys_nice",
+    "sys_resource",
+    "sys_time",
+    "sys_tty_config",
+    "mknod",
+    "lease",
+    "audit_write",
+    "audit_control",
+    "setfcap",
+    "mac_override",
+    "mac_admin",
+    "syslog",
+    "wake_alarm",
+    "block_suspend",
+    "audit_read",
+    "perfmon",
+    "bpf",
+    "checkpoint_restore",
+];
+
+/// Decode a `security.capability` value into `getcap`-style text,
+/// e.g. `cap_net_bind_service,cap_net_raw=ep`.
+pub fn decode_capabilities(value: &[u8]) -> Option<String> {
+    let word = |i: usize| -> Option<u64> {
+        let b = value.get(i * 4..i * 4 + 4)?;
+        Some(u32::from_le_bytes(b.try_into().ok()?) as u64)
+    };
+    let magic = word(0)?;
+    let effective = magic & 0x1 != 0;
+    let (permitted, inheritable, rootid) = match magic & 0xFF00_0000 {
+        0x0100_0000 => (word(1)?, word(2)?, None),
+        0x0200_0000 => (word(1)? | word(3)? << 32, word(2)? | word(4)? << 32, None),
+        0x0300_0000 => (
+            word(1)? | word(3)? << 32,
+            word(2)? | word(4)? << 32,
+            word(5),
+        ),
+        _ => return None,
+    };
+
+    // Group capabilities sharing the same flag set, like getcap does
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for bit in 0..64 {
+        let (p, i) = (permitted >> bit & 1 == 1, inheritable >> bit & 1 == 1);
+        if !p && !i {
+            continue;
+        }
+        let mut flags = String::new();
+        if effective && p {
+            flags.push('e');
+        }
+        if i {
+            flags.push('i');
+        }
+        if p {
+            flags.push('p');
+        }
+        let name = CAPABILITIES
+            .get(bit)
+            .map(|n| format!("cap_{}", n))
+            .unwrap_or_else(|| format!("cap_{}", bit));
+        groups.entry(flags).or_default().push(name);
+    }
+    let mut text = groups
+        .iter()
+        .map(|(flags, names)| format!("{}={}", names.join(","), flags))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if let Some(uid) = rootid.filter(|u| *u != 0) {
+        let _ = write!(text, " [rootid={}]", uid);
+    }
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_acl, decode_capabilities, format_flags, read_xattrs};
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn formats_immutable_and_append_only() {
+        assert_eq!(format_flags(0x10 | 0x20), "ia");
+        assert_eq!(format_flags(0), "-");
+    }
+
+    #[test]
+    fn decodes_posix_acl() {
+        let mut v = 2u32.to_le_bytes().to_vec();
+        for (tag, perm, id) in [
+            (0x01u16, 6u16, 0u32),
+            (0x02, 4, 1000),
+            (0x04, 4, 0),
+            (0x10, 4, 0),
+            (0x20, 0, 0),
+        ] {
+            v.extend_from_slice(&tag.to_le_bytes());
+            v.extend_from_slice(&perm.to_le_bytes());
+            v.extend_from_slice(&id.to_le_bytes());
+        }
+        assert_eq!(
+            decode_acl(&v).unwrap(),
+            "user::rw-,user:1000:r--,group::r--,mask::r--,other::---"
+        );
+    }
+
+    #[test]
+    fn decodes_file_capabilities() {
+        // `setcap cap_net_raw=ep`: VFS_CAP_REVISION_2 | effective, bit 13 permitted
+        let mut v = (0x0200_0001u32).to_le_bytes().to_vec();
+        for w in [1u32 << 13, 0, 0, 0] {
+            v.extend_from_slice(&w.to_le_bytes());
+        }
+        assert_eq!(decode_capabilities(&v).unwrap(), "cap_net_raw=ep");
+    }
+
+    #[test]
+    fn plain_file_has_readable_xattrs() {
+        let tmp = NamedTempFile::new().unwrap();
+        // May be empty or carry an SELinux label, but must not fail
+        read_xattrs(tmp.path()).unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+// gitignore-style filtering of watched paths (`ignore_patterns`)
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::{self, Path};
+
+/// Compiled `ignore_patterns` of a job.
+///
+/// Patterns follow `.gitignore` rules, rooted at `/`: a pattern without a
+/// slash (`*.swp`) matches a file name anywhere, a leading `/` anchors it to
+/// an absolute path, `**` spans directories, a trailing `/` only matches
+/// directories (and everything below them) and `!` re-includes a path.
+#[derive(Debug, Clone)]
+pub struct IgnoreMatcher {
+    inner: Gitignore,
+}
+
+impl Default for IgnoreMatcher {
+    fn default() -> Self {
+        IgnoreMatcher {
+            inner: Gitignore::empty(),
+        }
+    }
+}
+
+impl IgnoreMatcher {
+    /// Compile a list of gitignore-style patterns.
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new("/");
+        for pattern in patterns {
+            builder
+                .add_line(None, pattern)
+                .with_context(|| format!("invalid ignore pattern `{}`", pattern))?;
+        }
+        let inner = builder.build().context("building ignore patterns")?;
+        Ok(IgnoreMatcher { inner })
+    }
+
+    /// True if `path` (or one of its parent directories) is excluded.
+    /// `is_dir` must say whether `path` itself is a directory.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if self.inner.is_empty() {
+            return false;
+        }
+        // Patterns are rooted at `/`, so relative paths must be made absolute first.
+        let Ok(abs) = path::absolute(path) else {
+            return false;
+        };
+        self.inner
+            .matched_path_or_any_parents(&abs, is_dir)
+            .is_ignore()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IgnoreMatcher;
+    use std::path::Path;
+
+    fn matcher(patterns: &[&str]) -> IgnoreMatcher {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        IgnoreMatcher::new(&patterns).unwrap()
+    }
+
+    #[test]
+    fn basename_globs_match_anywhere() {
+        let m = matcher(&["*.tmp", "*.swp"]);
+        assert!(m.is_ignored(Path::new("/var/www/html/.index.php.swp"), false));
+        assert!(m.is_ignored(Path::new("/var/www/html/a/b/upload.tmp"), false));
+        assert!(!m.is_ignored(Path::new("/var/www/html/index.php"), false));
+    }
+
+    #[test]
+    fn double_star_and_negation() {
+        let m = matcher(&["/var/www/**/cache/*", "!/var/www/**/cache/keep.txt"]);
+        assert!(m.is_ignored(Path::new("/var/www/a/b/cache/x.bin"), false));
+        assert!(!m.is_ignored(Path::new("/var/www/a/b/cache/keep.txt"), false));
+    }
+
+    #[test]
+    fn directory_only_patterns_cover_contents() {
+        let m = matcher(&["build/"]);
+        assert!(m.is_ignored(Path::new("/srv/app/build"), true));
+        assert!(m.is_ignored(Path::new("/srv/app/build/out.o"), false));
+        // A *file* called `build` is not a directory match
+        assert!(!m.is_ignored(Path::new("/srv/app/build"), false));
+    }
+
+    #[test]
+    fn invalid_pattern_errors() {
+        let err = IgnoreMatcher::new(&["{a,b".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("invalid ignore pattern"));
+    }
+}
@@ -0,0 +1,309 @@
+//! Stat-keyed cache of file entries (`hashcache_<job_name>.json`), shared by polling jobs
+//! and baseline generation: a regular file whose inode, size, mtime and ctime are the same
+//! as when it was last hashed is not read again.
+
+use crate::integrity::signing::{Keys, Signature};
+use crate::integrity::{Entry, EntryOptions, FileKind};
+use crate::utils::write_atomic;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, Metadata};
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Files whose status changed less than this long (in nanoseconds) before they were
+/// hashed are not cached: on filesystems with coarse timestamps, another write in the
+/// same tick would leave their stat unchanged.
+const RACY_WINDOW_NS: i64 = 2_000_000_000;
+
+/// Where the hash cache of `job_name` is stored (`hashcache_<job_name>.json` in the cwd).
+pub fn path_for(job_name: &str) -> PathBuf {
+    PathBuf::from(format!("hashcache_{}.json", job_name))
+}
+
+fn nanos(secs: i64, nsec: i64) -> i64 {
+    secs * 1_000_000_000 + nsec
+}
+
+/// The cache file: the entries, signed like baselines when signing is configured.
+#[derive(Debug, Deserialize)]
+struct CacheFile {
+    entries: BTreeMap<String, Entry>,
+    #[serde(default)]
+    signature: Option<Signature>,
+}
+
+/// What is signed: the entries, prefixed so a cache signature never passes for a
+/// baseline signature.
+fn signed_bytes(entries: &BTreeMap<String, Entry>) -> Result<Vec<u8>> {
+    let mut bytes = b"watchdogfs hash cache\n".to_vec();
+    serde_json::to_writer(&mut bytes, entries).context("serializing hash cache")?;
+    Ok(bytes)
+}
+
+#[derive(Debug)]
+pub struct HashCache {
+    file: PathBuf,
+    entries: BTreeMap<String, Entry>,
+    /// Changed since loaded or saved
+    dirty: bool,
+    /// `RACY_WINDOW_NS`, lowered by tests
+    racy_window_ns: i64,
+    /// The job's signing keys: the file is only trusted if signed with them
+    keys: Option<Keys>,
+}
+
+impl HashCache {
+    /// The cache stored at `file`. Missing or unreadable caches start out empty, as
+    /// they only save work. With `keys` (signing configured), the file must carry a
+    /// valid signature, or anyone able to write it could vouch for a tampered file;
+    /// keys that can only verify disable the cache altogether.
+    pub fn load(file: PathBuf, keys: Option<&Keys>) -> Self {
+        let mut cache = HashCache {
+            file,
+            entries: BTreeMap::new(),
+            dirty: false,
+            racy_window_ns: RACY_WINDOW_NS,
+            keys: keys.cloned(),
+        };
+        if !cache.enabled() {
+            return cache;
+        }
+        let Some(stored) = fs::read(&cache.file)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<CacheFile>(&data).ok())
+        else {
+            return cache;
+        };
+        if let Some(keys) = keys {
+            let verified = signed_bytes(&stored.entries)
+                .and_then(|bytes| keys.verify(&bytes, stored.signature.as_ref()));
+            if let Err(e) = verified {
+                eprintln!("Ignoring hash cache {:?}: {}", cache.file, e);
+                return cache;
+            }
+        }
+        cache.entries = stored.entries;
+        cache
+    }
+
+    /// False when signing is configured with keys that cannot sign: an unsigned cache
+    /// would not be trusted, so files are always hashed.
+    fn enabled(&self) -> bool {
+        self.keys.as_ref().is_none_or(Keys::can_sign)
+    }
+
+    /// The entry `Entry::from_path` would compute for `path`, taken from the cache if
+    /// the file's stat is unchanged and the cached entry has everything `opts` asks for.
+    pub fn entry(&mut self, path: &str, opts: &EntryOptions) -> Result<Entry> {
+        if !self.enabled() {
+            return Entry::from_path(path, opts);
+        }
+        let meta = fs::symlink_metadata(path).with_context(|| format!("stat {}", path))?;
+        if let Some(cached) = self.entries.get(path)
+            && reusable(cached, &meta, opts)
+        {
+            let mut entry = cached.clone();
+            entry
+                .hashes
+                .retain(|alg, _| opts.hash_algorithms.contains(alg));
+            return Ok(entry);
+        }
+
+        let entry = Entry::from_path(path, opts)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as i64);
+        let settled = entry
+            .ctime
+            .is_some_and(|ctime| now - ctime >= self.racy_window_ns);
+        if meta.file_type().is_file() && settled {
+            self.entries.insert(path.to_string(), entry.clone());
+            self.dirty = true;
+        } else if self.entries.remove(path).is_some() {
+            self.dirty = true;
+        }
+        Ok(entry)
+    }
+
+    /// Drop the entries of paths `keep` rejects, e.g. files that no longer exist.
+    pub fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        let before = self.entries.len();
+        self.entries.retain(|path, _| keep(path));
+        self.dirty |= self.entries.len() != before;
+    }
+
+    /// Forget everything, so every file is hashed again.
+    pub fn clear(&mut self) {
+        self.dirty |= !self.entries.is_empty();
+        self.entries.clear();
+    }
+
+    /// Write the cache back to its file, signed if keys are configured, if anything
+    /// changed.
+    pub fn save(&mut self) -> Result<()> {
+        if !self.dirty || !self.enabled() {
+            return Ok(());
+        }
+        let signature = match &self.keys {
+            Some(keys) => Some(keys.sign(&signed_bytes(&self.entries)?)?),
+            None => None,
+        };
+        #[derive(Serialize)]
+        struct FileRef<'a> {
+            entries: &'a BTreeMap<String, Entry>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            signature: Option<Signature>,
+        }
+        let json = serde_json::to_vec(&FileRef {
+            entries: &self.entries,
+            signature,
+        })
+        .context("serializing hash cache")?;
+        write_atomic(&self.file, &json)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// Whether `cached` still describes the regular file `meta` was read from, with the
+/// digests, hash scope and extended data `opts` asks for. Changing the mode, owner,
+/// xattrs or inode flags of a file updates its ctime.
+fn reusable(cached: &Entry, meta: &Metadata, opts: &EntryOptions) -> bool {
+    meta.file_type().is_file()
+        && cached.file_type == Some(FileKind::File)
+        && cached.inode == Some(meta.ino())
+        && cached.size == Some(meta.size())
+        && cached.mtime == Some(nanos(meta.mtime(), meta.mtime_nsec()))
+        && cached.ctime == Some(nanos(meta.ctime(), meta.ctime_nsec()))
+        && cached.hashed == opts.scope_for(meta.size())
+        && opts
+            .hash_algorithms
+            .iter()
+            .all(|alg| cached.hashes.contains_key(alg))
+        && cached.xattrs.is_some() == opts.xattrs
+        && cached.flags.is_some() == opts.flags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HashCache;
+    use crate::config::HashAlgorithm;
+    use crate::integrity::signing::Keys;
+    use crate::integrity::{Entry, EntryOptions};
+    use ed25519_dalek::SigningKey;
+    use std::fs::{self, File, FileTimes};
+    use tempfile::tempdir;
+
+    #[test]
+    fn unchanged_stat_reuses_the_cached_entry() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("app.conf");
+        fs::write(&file, "debug = false").unwrap();
+        let path = file.to_str().unwrap();
+        let cache_file = dir.path().join("hashcache_web.json");
+        let opts = EntryOptions::default();
+
+        // Too fresh to be cached
+        let mut cache = HashCache::load(cache_file.clone(), None);
+        cache.entry(path, &opts).unwrap();
+        assert!(cache.entries.is_empty());
+
+        cache.racy_window_ns = 0;
+        let entry = cache.entry(path, &opts).unwrap();
+        assert_eq!(entry, Entry::from_path(path, &opts).unwrap());
+        cache.save().unwrap();
+
+        // A digest planted in the cache is believed while the stat matches...
+        let mut cache = HashCache::load(cache_file, None);
+        let planted = "00".repeat(32);
+        cache
+            .entries
+            .get_mut(path)
+            .unwrap()
+            .hashes
+            .insert(HashAlgorithm::Sha256, planted.clone());
+        assert_eq!(
+            cache.entry(path, &opts).unwrap().digest(),
+            Some(planted.as_str())
+        );
+
+        // ...but not once the file changes, even keeping its size and mtime
+        let mtime = fs::metadata(&file).unwrap().modified().unwrap();
+        fs::write(&file, "debug = truee").unwrap();
+        File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_times(FileTimes::new().set_modified(mtime))
+            .unwrap();
+        assert_eq!(
+            cache.entry(path, &opts).unwrap(),
+            Entry::from_path(path, &opts).unwrap()
+        );
+    }
+
+    #[test]
+    fn with_signing_only_a_signed_cache_is_trusted() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("app.conf");
+        fs::write(&file, "debug = false").unwrap();
+        let path = file.to_str().unwrap();
+        let cache_file = dir.path().join("hashcache_web.json");
+        let opts = EntryOptions::default();
+        let keys = Keys::Hmac(b"0123456789abcdef".to_vec());
+        let trusted = |keys: Option<&Keys>| HashCache::load(cache_file.clone(), keys).entries.len();
+
+        let mut cache = HashCache::load(cache_file.clone(), Some(&keys));
+        cache.racy_window_ns = 0;
+        cache.entry(path, &opts).unwrap();
+        cache.save().unwrap();
+        assert_eq!(trusted(Some(&keys)), 1);
+
+        // A digest planted in the file breaks the signature, so the cache is dropped
+        let json = fs::read_to_string(&cache_file).unwrap();
+        let entry = Entry::from_path(path, &opts).unwrap();
+        let planted = json.replace(entry.digest().unwrap(), &"00".repeat(32));
+        fs::write(&cache_file, planted).unwrap();
+        assert_eq!(trusted(Some(&keys)), 0);
+
+        // So is an unsigned cache, and keys that cannot sign never use one
+        let mut unsigned = HashCache::load(cache_file.clone(), None);
+        unsigned.racy_window_ns = 0;
+        unsigned.entries.clear();
+        unsigned.entry(path, &opts).unwrap();
+        unsigned.save().unwrap();
+        assert_eq!(trusted(None), 1);
+        assert_eq!(trusted(Some(&keys)), 0);
+        let verifying = SigningKey::from_bytes(&[7; 32]).verifying_key();
+        let verify_only = Keys::Ed25519 {
+            signing: None,
+            verifying,
+        };
+        let mut cache = HashCache::load(cache_file, Some(&verify_only));
+        cache.racy_window_ns = 0;
+        cache.entry(path, &opts).unwrap();
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn missing_digests_are_computed() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("app.conf");
+        fs::write(&file, "debug = false").unwrap();
+        let path = file.to_str().unwrap();
+        let mut cache = HashCache::load(dir.path().join("hashcache_web.json"), None);
+        cache.racy_window_ns = 0;
+        cache.entry(path, &EntryOptions::default()).unwrap();
+
+        let opts = EntryOptions {
+            hash_algorithms: vec![HashAlgorithm::Blake3],
+            ..Default::default()
+        };
+        let entry = cache.entry(path, &opts).unwrap();
+        assert_eq!(entry, Entry::from_path(path, &opts).unwrap());
+        assert_eq!(entry.hashes.len(), 1);
+    }
+}
@@ -0,0 +1,251 @@
+// Timestamped snapshots of each job's baseline (`watchdogfs history`)
+use crate::config::Config;
+use crate::integrity::signing::Keys;
+use crate::integrity::{
+    BaselineFile, Change, baseline_path, compare, lock_baseline, read_baseline,
+    read_baseline_unverified,
+};
+use crate::utils::write_atomic;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Why a snapshot was taken.
+pub const REASON_BASELINE: &str = "baseline";
+pub const REASON_ACCEPT: &str = "accept";
+pub const REASON_ROLLBACK: &str = "rollback";
+
+/// Where the snapshots of a job are kept.
+pub fn history_dir(job_name: &str) -> PathBuf {
+    Path::new("history").join(job_name)
+}
+
+/// One stored copy of a baseline file, named `<id>-<reason>.json`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// UTC timestamp (`20240102T030405.678Z`); sorts chronologically
+    pub id: String,
+    pub reason: String,
+    pub path: PathBuf,
+}
+
+impl Snapshot {
+    fn from_path(path: PathBuf) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.strip_suffix(".json")?;
+        let (id, reason) = name.split_once('-')?;
+        Some(Snapshot {
+            id: id.to_string(),
+            reason: reason.to_string(),
+            path: path.clone(),
+        })
+    }
+
+    /// The snapshot's baseline file of `job_name`, verified with `keys` if given.
+    pub fn read(&self, job_name: &str, keys: Option<&Keys>) -> Result<BaselineFile> {
+        read_baseline(&self.path, job_name, keys)
+    }
+}
+
+/// Copy the current `baseline_file` into `dir` as a new snapshot, then drop the
+/// oldest snapshots beyond `keep` (if set).
+pub fn record(
+    dir: &Path,
+    baseline_file: &Path,
+    reason: &str,
+    keep: Option<usize>,
+) -> Result<Snapshot> {
+    let data = fs::read(baseline_file).with_context(|| format!("reading {:?}", baseline_file))?;
+    fs::create_dir_all(dir).with_context(|| format!("creating history directory {:?}", dir))?;
+
+    // Ids are millisecond timestamps and must be unique whatever the reason, or an
+    // id would name two snapshots; wait out a collision rather than share one
+    let taken: Vec<String> = list(dir)?.into_iter().map(|s| s.id).collect();
+    let path = loop {
+        let id = chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ").to_string();
+        if !taken.contains(&id) {
+            break dir.join(format!("{}-{}.json", id, reason));
+        }
+        thread::sleep(Duration::from_millis(1));
+    };
+    write_atomic(&path, &data)?;
+
+    if let Some(keep) = keep {
+        let snapshots = list(dir)?;
+        let excess = snapshots.len().saturating_sub(keep.max(1));
+        for old in &snapshots[..excess] {
+            fs::remove_file(&old.path)
+                .with_context(|| format!("pruning snapshot {:?}", old.path))?;
+        }
+    }
+    Snapshot::from_path(path).context("snapshot name does not parse")
+}
+
+/// All snapshots in `dir`, oldest first. A missing directory is an empty history.
+pub fn list(dir: &Path) -> Result<Vec<Snapshot>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut snapshots = Vec::new();
+    for dirent in fs::read_dir(dir).with_context(|| format!("reading {:?}", dir))? {
+        if let Some(snapshot) = Snapshot::from_path(dirent?.path()) {
+            snapshots.push(snapshot);
+        }
+    }
+    snapshots.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(snapshots)
+}
+
+/// The snapshot whose id starts with `id`; `latest` names the newest one. The
+/// prefix must be unambiguous.
+pub fn find(dir: &Path, id: &str) -> Result<Snapshot> {
+    let snapshots = list(dir)?;
+    if id == "latest" {
+        return snapshots
+            .last()
+            .cloned()
+            .with_context(|| format!("no snapshots in {:?}", dir));
+    }
+    let mut found = snapshots.into_iter().filter(|s| s.id.starts_with(id));
+    match (found.next(), found.next()) {
+        (Some(snapshot), None) => Ok(snapshot),
+        (None, _) => anyhow::bail!("no snapshot '{}' in {:?}", id, dir),
+        (Some(_), Some(_)) => anyhow::bail!("snapshot id '{}' is ambiguous", id),
+    }
+}
+
+/// Make `snapshot` the active `baseline_file` again, byte for byte, after checking
+/// its signature with `keys`. The rollback itself is recorded as a new snapshot.
+pub fn rollback(
+    dir: &Path,
+    baseline_file: &Path,
+    job_name: &str,
+    snapshot: &Snapshot,
+    keys: Option<&Keys>,
+    keep: Option<usize>,
+) -> Result<Snapshot> {
+    snapshot
+        .read(job_name, keys)
+        .with_context(|| format!("loading snapshot {}", snapshot.id))?;
+    let data = fs::read(&snapshot.path).with_context(|| format!("reading {:?}", snapshot.path))?;
+    // Held through `record`, like `accept`, so a running watcher cannot rewrite the
+    // baseline between the two
+    let _lock = lock_baseline(baseline_file)?;
+    write_atomic(baseline_file, &data)?;
+    record(dir, baseline_file, REASON_ROLLBACK, keep)
+}
+
+/// What a listing shows about a snapshot.
+#[derive(Debug)]
+pub struct Summary {
+    pub entries: usize,
+    /// When this version of the baseline was written
+    pub written_at: String,
+    /// Identical to the active baseline file
+    pub active: bool,
+}
+
+pub fn summary(snapshot: &Snapshot, baseline_file: &Path) -> Result<Summary> {
+    let file = read_baseline_unverified(&snapshot.path)?;
+    let active = fs::read(baseline_file).ok() == Some(fs::read(&snapshot.path)?);
+    Ok(Summary {
+        entries: file.entries.len(),
+        written_at: if file.header.updated_at.is_empty() {
+            file.header.created_at
+        } else {
+            file.header.updated_at
+        },
+        active,
+    })
+}
+
+/// Changes from snapshot `from` to snapshot `to` (the active baseline if `None`) of
+/// `job_name`, compared on the job's attributes. Both sides are verified when
+/// signing is configured.
+pub fn diff(cfg: &Config, job_name: &str, from: &str, to: Option<&str>) -> Result<Vec<Change>> {
+    let job_cfg = cfg
+        .jobs
+        .get(job_name)
+        .with_context(|| format!("No job named '{}' in the configuration", job_name))?;
+    let keys = Keys::for_config(cfg)?;
+    let dir = history_dir(job_name);
+
+    let old = find(&dir, from)?
+        .read(job_name, keys.as_ref())
+        .with_context(|| format!("loading snapshot {}", from))?;
+    let new = match to {
+        Some(id) => find(&dir, id)?
+            .read(job_name, keys.as_ref())
+            .with_context(|| format!("loading snapshot {}", id))?,
+        None => read_baseline(&baseline_path(job_name), job_name, keys.as_ref())
+            .with_context(|| format!("loading baseline for job '{}'", job_name))?,
+    };
+    Ok(compare(&old.entries, &new.entries, &job_cfg.attributes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{REASON_ACCEPT, REASON_BASELINE, find, list, record, rollback};
+    use crate::config::JobConfig;
+    use crate::integrity::{Baseline, load_baseline, save_baseline};
+    use tempfile::tempdir;
+
+    #[test]
+    fn record_list_and_rollback() {
+        let state = tempdir().unwrap();
+        let file = state.path().join("baseline_web.json");
+        let dir = state.path().join("history");
+        let cfg = JobConfig::default();
+
+        let mut first = Baseline::new();
+        first.insert("/etc/nginx/nginx.conf".into(), "aa".into());
+        save_baseline(&file, &first, "web", &cfg, None).unwrap();
+        let snap = record(&dir, &file, REASON_BASELINE, None).unwrap();
+
+        let mut second = first.clone();
+        second.insert("/var/www/shell.php".into(), "bb".into());
+        save_baseline(&file, &second, "web", &cfg, None).unwrap();
+        record(&dir, &file, REASON_ACCEPT, None).unwrap();
+
+        let reasons: Vec<_> = list(&dir).unwrap().into_iter().map(|s| s.reason).collect();
+        assert_eq!(reasons, ["baseline", "accept"]);
+        let err = find(&dir, &snap.id[..8]).unwrap_err();
+        assert!(err.to_string().contains("ambiguous"));
+        assert_eq!(find(&dir, &snap.id).unwrap(), snap);
+
+        rollback(&dir, &file, "web", &snap, None, None).unwrap();
+        assert_eq!(load_baseline(&file, "web", None).unwrap(), first);
+        assert_eq!(find(&dir, "latest").unwrap().reason, "rollback");
+    }
+
+    #[test]
+    fn ids_are_unique_across_reasons() {
+        let state = tempdir().unwrap();
+        let file = state.path().join("baseline_web.json");
+        let dir = state.path().join("history");
+        save_baseline(&file, &Baseline::new(), "web", &JobConfig::default(), None).unwrap();
+        for _ in 0..10 {
+            record(&dir, &file, REASON_BASELINE, None).unwrap();
+            record(&dir, &file, REASON_ACCEPT, None).unwrap();
+        }
+        let mut ids: Vec<_> = list(&dir).unwrap().into_iter().map(|s| s.id).collect();
+        ids.dedup();
+        assert_eq!(ids.len(), 20);
+    }
+
+    #[test]
+    fn keep_prunes_the_oldest() {
+        let state = tempdir().unwrap();
+        let file = state.path().join("baseline_web.json");
+        let dir = state.path().join("history");
+        save_baseline(&file, &Baseline::new(), "web", &JobConfig::default(), None).unwrap();
+        let first = record(&dir, &file, REASON_BASELINE, Some(2)).unwrap();
+        for _ in 0..3 {
+            record(&dir, &file, REASON_ACCEPT, Some(2)).unwrap();
+        }
+        let snapshots = list(&dir).unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert!(!snapshots.contains(&first));
+    }
+}
@@ -1,16 +1,42 @@
 // Integrity checking module
 
+pub mod accept;
 mod baseline;
+mod change;
+pub mod check;
 mod checksum;
+pub mod diff;
+mod entry;
+pub mod extattr;
+mod filter;
+pub mod hashcache;
+pub mod history;
+pub mod nagios;
+pub mod restore;
+pub mod semantic;
+pub mod signing;
+pub mod store;
+pub mod textdiff;
+mod walk;
 
 use crate::config::{Config, write_default};
 use anyhow::{Context, Result};
-use serde_json;
+use hashcache::HashCache;
+use signing::Keys;
 use serde_yaml;
 use std::fs;
 
-pub use baseline::{Baseline, generate as generate_map};
+pub use baseline::{
+    Baseline, BaselineFile, Header, SCHEMA_VERSION, config_hash, create as create_baseline,
+    generate as generate_map, generate_cached as generate_map_cached, load as load_baseline,
+    lock as lock_baseline, path_for as baseline_path, read as read_baseline,
+    read_unverified as read_baseline_unverified, save as save_baseline, scan,
+};
+pub use change::{Change, ChangeKind, compare};
 pub use checksum::*;
+pub use entry::{AttributeChange, Entry, EntryOptions, FileKind};
+pub use filter::IgnoreMatcher;
+pub use walk::{WalkOptions, collect_files, is_watched};
 
 /// To write a default config and auto‐generate all baselines.
 pub fn init(files: Vec<String>) -> Result<()> {
@@ -46,31 +72,96 @@ pub fn generate_baseline() -> Result<()> {
         .context("Failed to read `config.yaml` in current directory")?;
     let cfg: Config =
         serde_yaml::from_str(&cfg_str).context("Failed to parse `config.yaml` as YAML")?;
+    cfg.validate()?;
+
+    let keys = Keys::for_config(&cfg)?;
 
     // 2) For each job, generate (or regenerate) that job’s baseline
     for (job_name, job_cfg) in &cfg.jobs {
         // Generate the baseline map for this job’s watch_paths
-        let baseline_map = generate_map(&job_cfg.watch_paths)
-            .with_context(|| format!("Failed to generate baseline for job '{}'", job_name))?;
-
-        // Serialize & write to `baseline_<job_name>.json`
-        let filename = format!("baseline_{}.json", job_name);
-        let json = serde_json::to_string_pretty(&baseline_map)
-            .with_context(|| format!("Failed to serialize baseline for job '{}'", job_name))?;
+        let walk_opts = WalkOptions::for_job(job_cfg)
+            .with_context(|| format!("Invalid ignore_patterns for job '{}'", job_name))?;
+        let entry_opts = EntryOptions::for_job(job_cfg);
+        let failed = || format!("Failed to generate baseline for job '{}'", job_name);
+        let baseline_map = if keys.is_some() {
+            // A signed baseline vouches for every digest in it, so hash everything
+            generate_map(&job_cfg.watch_paths, &walk_opts, &entry_opts).with_context(failed)?
+        } else {
+            // Files unchanged since the last run or poll are not hashed again
+            let mut cache = HashCache::load(hashcache::path_for(job_name), None);
+            let baseline_map =
+                generate_map_cached(&job_cfg.watch_paths, &walk_opts, &entry_opts, &mut cache)
+                    .with_context(failed)?;
+            cache
+                .save()
+                .with_context(|| format!("Failed to write hash cache for job '{}'", job_name))?;
+            baseline_map
+        };
 
-        fs::write(&filename, json).with_context(|| {
+        // Serialize & write to `baseline_<job_name>.json`, under the lock a running
+        // daemon and `accept` take, held until the history has a copy of this file
+        let filename = baseline_path(job_name);
+        let lock = lock_baseline(&filename)
+            .with_context(|| format!("Failed to lock the baseline of job '{}'", job_name))?;
+        create_baseline(&filename, &baseline_map, job_name, job_cfg, keys.as_ref()).with_context(|| {
             format!(
-                "Failed to write baseline file `{}` for job '{}'",
+                "Failed to write baseline file {:?} for job '{}'",
                 filename, job_name
             )
         })?;
+        history::record(
+            &history::history_dir(job_name),
+            &filename,
+            history::REASON_BASELINE,
+            cfg.history_keep,
+        )
+        .with_context(|| format!("Failed to record history for job '{}'", job_name))?;
+        drop(lock);
+
+        if let Some(store) = store::ContentStore::for_job(&cfg, job_cfg) {
+            store.put_all(&baseline_map);
+        }
 
         println!(
             "✅ Baseline for job '{}' generated and saved to {}",
-            job_name, filename
+            job_name,
+            filename.display()
         );
     }
 
+    store::prune_config(&cfg).context("Failed to prune the content store")?;
+
+    Ok(())
+}
+
+/// Sign the existing baseline of every job (or only `job`) with the configured key,
+/// without re-scanning the disk. Used after enabling signing or rotating keys; the
+/// entries are trusted as they are, so only run it on baselines known to be good.
+pub fn resign_baselines(cfg: &Config, job: Option<&str>) -> Result<()> {
+    let keys = Keys::for_config(cfg)?.context("no `signing` section in the configuration")?;
+
+    if let Some(name) = job
+        && !cfg.jobs.contains_key(name)
+    {
+        anyhow::bail!("No job named '{}' in the configuration", name);
+    }
+    for (job_name, job_cfg) in &cfg.jobs {
+        if job.is_some_and(|j| j != job_name) {
+            continue;
+        }
+        let filename = baseline_path(job_name);
+        let _lock = lock_baseline(&filename)
+            .with_context(|| format!("Failed to lock the baseline of job '{}'", job_name))?;
+        let file = read_baseline_unverified(&filename)
+            .with_context(|| format!("Failed to read baseline for job '{}'", job_name))?;
+        save_baseline(&filename, &file.entries, job_name, job_cfg, Some(&keys))
+            .with_context(|| format!("Failed to write baseline for job '{}'", job_name))?;
+        println!(
+            "✅ Baseline for job '{}' signed with {}",
+            job_name,
+            keys.method().as_str()
+        );
+    }
     Ok(())
 }
 
@@ -0,0 +1,181 @@
+// Nagios/Icinga plugin output for a check report (`watchdogfs nagios`)
+use crate::config::{Config, NagiosThresholds};
+use crate::integrity::ChangeKind;
+use crate::integrity::check::{JobReport, Report};
+use std::fmt::Write as _;
+
+/// Plugin states, ordered so that the worst job decides the overall state: a job
+/// that could not be checked must not hide a CRITICAL one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum State {
+    Ok,
+    Warning,
+    Unknown,
+    Critical,
+}
+
+impl State {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            State::Ok => "OK",
+            State::Warning => "WARNING",
+            State::Unknown => "UNKNOWN",
+            State::Critical => "CRITICAL",
+        }
+    }
+
+    /// Standard plugin exit code.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            State::Ok => 0,
+            State::Warning => 1,
+            State::Critical => 2,
+            State::Unknown => 3,
+        }
+    }
+}
+
+fn job_state(job: &JobReport, limits: NagiosThresholds) -> State {
+    let n = job.changes.len();
+    if job.error.is_some() {
+        State::Unknown
+    } else if n > 0 && n >= limits.critical {
+        State::Critical
+    } else if n > 0 && n >= limits.warning {
+        State::Warning
+    } else {
+        State::Ok
+    }
+}
+
+/// Plugin output for a failure that prevented any check (bad config, unknown job).
+pub fn unknown(error: &anyhow::Error) -> (String, i32) {
+    let state = State::Unknown;
+    (
+        format!("WATCHDOGFS {} - {:#}\n", state.as_str(), error),
+        state.exit_code(),
+    )
+}
+
+/// Render `report` as a status line with perfdata followed by one line per
+/// change, and return it with the plugin exit code. Thresholds come from each
+/// job's `nagios` section in `cfg`.
+pub fn render(report: &Report, cfg: &Config) -> (String, i32) {
+    let mut state = State::Ok;
+    let mut summary = Vec::new();
+    let mut perfdata = Vec::new();
+    let mut details = String::new();
+
+    for job in &report.jobs {
+        let limits = cfg.jobs.get(&job.job).map(|j| j.nagios).unwrap_or_default();
+        let job_state = job_state(job, limits);
+        state = state.max(job_state);
+
+        summary.push(match &job.error {
+            Some(e) => format!("{}: {}", job.job, e),
+            None if job.changes.is_empty() => format!("{}: ok", job.job),
+            None => format!("{}: {} change(s)", job.job, job.changes.len()),
+        });
+        if job.error.is_none() {
+            let missing = job
+                .changes
+                .iter()
+                .filter(|c| c.kind == ChangeKind::Deleted)
+                .count();
+            perfdata.push(format!("'{}_checked'={};;;0", job.job, job.files));
+            perfdata.push(format!(
+                "'{}_changed'={};{};{};0",
+                job.job,
+                job.changes.len(),
+                limits.warning,
+                limits.critical
+            ));
+            perfdata.push(format!("'{}_missing'={};;;0", job.job, missing));
+        }
+        for change in &job.changes {
+            let _ = write!(
+                details,
+                "{}: {} {}",
+                job.job,
+                change.kind.as_str(),
+                change.path
+            );
+            if let Some(from) = &change.from {
+                let _ = write!(details, " (from {})", from);
+            }
+            details.push('\n');
+        }
+    }
+
+    if summary.is_empty() {
+        summary.push("no jobs configured".to_string());
+    }
+    let mut out = format!("WATCHDOGFS {} - {}", state.as_str(), summary.join(", "));
+    if !perfdata.is_empty() {
+        let _ = write!(out, " | {}", perfdata.join(" "));
+    }
+    out.push('\n');
+    out.push_str(&details);
+    (out, state.exit_code())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{State, render};
+    use crate::config::{Config, JobConfig, NagiosThresholds};
+    use crate::integrity::check::{JobReport, Report, Status};
+    use crate::integrity::{Change, Entry};
+    use std::collections::HashMap;
+
+    fn report(changes: usize, error: Option<&str>) -> Report {
+        let job = JobReport {
+            job: "web".into(),
+            status: Status::Changed,
+            files: 10,
+            changes: (0..changes)
+                .map(|i| Change::deleted(format!("/srv/{}", i), Entry::from("00")))
+                .collect(),
+            error: error.map(str::to_string),
+        };
+        Report {
+            status: job.status,
+            jobs: vec![job],
+        }
+    }
+
+    fn config(warning: usize, critical: usize) -> Config {
+        let job = JobConfig {
+            nagios: NagiosThresholds { warning, critical },
+            ..Default::default()
+        };
+        Config {
+            jobs: HashMap::from([("web".to_string(), job)]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn thresholds_pick_the_state() {
+        let cfg = config(1, 3);
+        assert_eq!(render(&report(0, None), &cfg).1, State::Ok.exit_code());
+        assert_eq!(render(&report(2, None), &cfg).1, State::Warning.exit_code());
+        assert_eq!(
+            render(&report(3, None), &cfg).1,
+            State::Critical.exit_code()
+        );
+        assert_eq!(render(&report(0, Some("gone")), &cfg).1, 3);
+    }
+
+    #[test]
+    fn status_line_carries_perfdata_and_details() {
+        let (out, code) = render(&report(2, None), &config(1, 1));
+        let mut lines = out.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "WATCHDOGFS CRITICAL - web: 2 change(s) | \
+             'web_checked'=10;;;0 'web_changed'=2;1;1;0 'web_missing'=2;;;0"
+        );
+        assert_eq!(lines.next().unwrap(), "web: deleted /srv/0");
+        assert_eq!(code, 2);
+    }
+}
@@ -0,0 +1,238 @@
+// Writing tampered files back from the content store (`remediation: restore`)
+use crate::config::RestoreLimit;
+use crate::integrity::store::ContentStore;
+use crate::integrity::{Entry, FileKind};
+use anyhow::{Context, Result};
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::{File, OpenOptions, Permissions};
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Replace `path` with the content recorded as `entry`, taken from `store`, with the
+/// recorded owner and mode. The file is written next to `path` under a random name and
+/// renamed over it, so readers never see a partial file. Both happen relative to the
+/// directory's descriptor, and the temporary file is created exclusively without
+/// following symlinks, so nobody who can write to the directory can redirect the
+/// write (restores run as root).
+pub fn restore(path: &str, entry: &Entry, store: &ContentStore) -> Result<()> {
+    if entry.file_type.is_some_and(|t| t != FileKind::File) {
+        anyhow::bail!("only regular files can be restored");
+    }
+    let data = store
+        .get_entry(entry)?
+        .context("no stored copy of the recorded content")?;
+
+    let target = Path::new(path);
+    let dir = target
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = target
+        .file_name()
+        .with_context(|| format!("{} has no file name", path))?;
+    let dir_fd = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY)
+        .open(dir)
+        .with_context(|| format!("opening directory {:?}", dir))?;
+
+    let (mut file, tmp) = create_temp(&dir_fd, name)
+        .with_context(|| format!("creating a temporary file in {:?}", dir))?;
+    let result = (|| -> Result<()> {
+        file.write_all(&data)?;
+        // chown clears setuid/setgid, so set the mode afterwards
+        if entry.uid.is_some() || entry.gid.is_some() {
+            let uid = entry.uid.unwrap_or(u32::MAX);
+            let gid = entry.gid.unwrap_or(u32::MAX);
+            // SAFETY: the descriptor is valid for the lifetime of `file`; u32::MAX (-1) leaves an id unchanged
+            if unsafe { libc::fchown(file.as_raw_fd(), uid, gid) } != 0 {
+                return Err(io::Error::last_os_error())
+                    .with_context(|| format!("changing owner of {:?}", tmp));
+            }
+        }
+        if let Some(mode) = entry.mode {
+            file.set_permissions(Permissions::from_mode(mode))?;
+        }
+        file.sync_all()?;
+        let name = CString::new(name.as_bytes())?;
+        // SAFETY: both names are NUL-terminated and the descriptor is open
+        let renamed = unsafe {
+            libc::renameat(
+                dir_fd.as_raw_fd(),
+                tmp.as_ptr(),
+                dir_fd.as_raw_fd(),
+                name.as_ptr(),
+            )
+        };
+        if renamed != 0 {
+            return Err(io::Error::last_os_error()).with_context(|| format!("replacing {}", path));
+        }
+        dir_fd
+            .sync_all()
+            .with_context(|| format!("syncing directory {:?}", dir))
+    })();
+    if result.is_err() {
+        // SAFETY: as above; a failure leaves nothing else to clean up
+        unsafe { libc::unlinkat(dir_fd.as_raw_fd(), tmp.as_ptr(), 0) };
+    }
+    result
+}
+
+/// Create `.<name>.watchdogfs-restore.<random>` in the directory open as `dir_fd`,
+/// mode `0600`. `O_EXCL | O_NOFOLLOW` refuses any file or symlink already there, in
+/// which case another random name is tried.
+fn create_temp(dir_fd: &File, name: &OsStr) -> Result<(File, CString)> {
+    for _ in 0..16 {
+        let tmp = CString::new(temp_name(name).into_vec())?;
+        // SAFETY: `tmp` is NUL-terminated and the descriptor is open; the returned
+        // descriptor is owned by the `File` built from it
+        let fd = unsafe {
+            libc::openat(
+                dir_fd.as_raw_fd(),
+                tmp.as_ptr(),
+                libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+                0o600 as libc::c_uint,
+            )
+        };
+        if fd >= 0 {
+            return Ok((unsafe { File::from_raw_fd(fd) }, tmp));
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::AlreadyExists {
+            return Err(err.into());
+        }
+    }
+    anyhow::bail!("every temporary name tried was taken")
+}
+
+fn temp_name(name: &OsStr) -> OsString {
+    let mut tmp = OsString::from(".");
+    tmp.push(name);
+    tmp.push(format!(".watchdogfs-restore.{:016x}", fastrand::u64(..)));
+    tmp
+}
+
+/// Counts recent restores per path, refusing more than `max_restores` within the
+/// window so a restore never fights another writer forever.
+#[derive(Debug)]
+pub struct RestoreGuard {
+    limit: RestoreLimit,
+    recent: HashMap<String, VecDeque<Instant>>,
+}
+
+impl RestoreGuard {
+    pub fn new(limit: RestoreLimit) -> Self {
+        RestoreGuard {
+            limit,
+            recent: HashMap::new(),
+        }
+    }
+
+    /// Whether `path` may be restored now; if so, the restore is counted.
+    pub fn allow(&mut self, path: &str, now: Instant) -> bool {
+        let window = Duration::from_secs(self.limit.window_secs);
+        let times = self.recent.entry(path.to_string()).or_default();
+        while times
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= window)
+        {
+            times.pop_front();
+        }
+        if times.len() >= self.limit.max_restores {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RestoreGuard, restore, temp_name};
+    use crate::config::{ContentStoreConfig, RestoreLimit};
+    use crate::integrity::store::ContentStore;
+    use crate::integrity::{Entry, EntryOptions};
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, Instant};
+    use tempfile::tempdir;
+
+    #[test]
+    fn restores_content_and_mode() {
+        let dir = tempdir().unwrap();
+        let store = ContentStore::new(ContentStoreConfig {
+            path: dir.path().join("store").to_str().unwrap().to_string(),
+            ..Default::default()
+        });
+        let file = dir.path().join("sudoers");
+        fs::write(&file, "root ALL=(ALL) ALL\n").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o440)).unwrap();
+        let path = file.to_str().unwrap();
+        let entry = Entry::from_path(path, &EntryOptions::default()).unwrap();
+        store.put(path, &entry).unwrap();
+
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o666)).unwrap();
+        fs::write(&file, "ALL ALL=(ALL) NOPASSWD: ALL\n").unwrap();
+        restore(path, &entry, &store).unwrap();
+        let now = Entry::from_path(path, &EntryOptions::default()).unwrap();
+        assert!(now.same_content(&entry));
+        assert_eq!(now.mode, Some(0o440));
+
+        fs::remove_file(&file).unwrap();
+        restore(path, &entry, &store).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "root ALL=(ALL) ALL\n");
+    }
+
+    #[test]
+    fn does_not_follow_a_symlink_at_the_temporary_name() {
+        let dir = tempdir().unwrap();
+        let store = ContentStore::new(ContentStoreConfig {
+            path: dir.path().join("store").to_str().unwrap().to_string(),
+            ..Default::default()
+        });
+        let file = dir.path().join("sudoers");
+        fs::write(&file, "root ALL=(ALL) ALL\n").unwrap();
+        let path = file.to_str().unwrap();
+        let entry = Entry::from_path(path, &EntryOptions::default()).unwrap();
+        store.put(path, &entry).unwrap();
+        fs::write(&file, "ALL ALL=(ALL) NOPASSWD: ALL\n").unwrap();
+
+        // Plant a symlink where the first temporary name will be
+        let victim = dir.path().join("shadow");
+        fs::write(&victim, "root:x:0:0\n").unwrap();
+        fastrand::seed(7);
+        let planted = dir.path().join(temp_name(OsStr::new("sudoers")));
+        std::os::unix::fs::symlink(&victim, &planted).unwrap();
+        fastrand::seed(7);
+
+        restore(path, &entry, &store).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "root ALL=(ALL) ALL\n");
+        assert_eq!(fs::read_to_string(&victim).unwrap(), "root:x:0:0\n");
+        assert!(
+            fs::symlink_metadata(&planted)
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+    }
+
+    #[test]
+    fn guard_stops_a_restore_loop() {
+        let mut guard = RestoreGuard::new(RestoreLimit {
+            max_restores: 2,
+            window_secs: 60,
+        });
+        let start = Instant::now();
+        assert!(guard.allow("/etc/sudoers", start));
+        assert!(guard.allow("/etc/sudoers", start + Duration::from_secs(1)));
+        assert!(!guard.allow("/etc/sudoers", start + Duration::from_secs(2)));
+        assert!(guard.allow("/etc/hosts", start + Duration::from_secs(2)));
+        assert!(guard.allow("/etc/sudoers", start + Duration::from_secs(61)));
+    }
+}
@@ -0,0 +1,336 @@
+// /etc/passwd, /etc/shadow and /etc/group
+use super::{Finding, Severity};
+use std::collections::BTreeMap;
+
+/// Groups whose members are effectively root.
+const ROOT_GROUPS: &[&str] = &["root", "wheel", "sudo", "admin"];
+/// Groups that grant access to logs, disks or containers.
+const SENSITIVE_GROUPS: &[&str] = &[
+    "adm",
+    "disk",
+    "docker",
+    "kmem",
+    "libvirt",
+    "lxd",
+    "shadow",
+    "systemd-journal",
+];
+
+/// Colon-separated records by their first field; the remaining fields are kept.
+fn records(text: &str) -> BTreeMap<&str, Vec<&str>> {
+    let mut records = BTreeMap::new();
+    for line in super::lines(text) {
+        let mut fields = line.split(':');
+        let name = fields.next().unwrap_or_default();
+        records.entry(name).or_insert_with(|| fields.collect());
+    }
+    records
+}
+
+fn field<'a>(fields: &[&'a str], i: usize) -> &'a str {
+    fields.get(i).copied().unwrap_or_default()
+}
+
+/// Whether a uid or gid field means root. The system parses ids as numbers, so
+/// `00` is root as much as `0`.
+fn is_root_id(id: &str) -> bool {
+    id.parse::<u32>() == Ok(0)
+}
+
+fn can_login(shell: &str) -> bool {
+    !(shell.ends_with("nologin") || shell.ends_with("false") || shell == "/bin/sync")
+}
+
+pub fn passwd(old: &str, new: &str) -> Vec<Finding> {
+    // fields after the name: password, uid, gid, gecos, home, shell
+    let (old, new) = (records(old), records(new));
+    let mut findings = Vec::new();
+    for (name, now) in &new {
+        let (uid, shell) = (field(now, 1), field(now, 5));
+        let Some(was) = old.get(name) else {
+            findings.push(if is_root_id(uid) {
+                Finding::new(
+                    Severity::Critical,
+                    format!("user {} added with uid {}", name, uid),
+                )
+            } else if can_login(shell) {
+                Finding::new(
+                    Severity::Warning,
+                    format!(
+                        "user {} added with uid {} and login shell {}",
+                        name, uid, shell
+                    ),
+                )
+            } else {
+                Finding::new(
+                    Severity::Info,
+                    format!("user {} added with uid {}", name, uid),
+                )
+            });
+            continue;
+        };
+        if field(was, 1) != uid {
+            let severity = if is_root_id(uid) {
+                Severity::Critical
+            } else {
+                Severity::Warning
+            };
+            findings.push(Finding::new(
+                severity,
+                format!("uid of {} changed from {} to {}", name, field(was, 1), uid),
+            ));
+        }
+        if field(was, 2) != field(now, 2) && is_root_id(field(now, 2)) {
+            findings.push(Finding::new(
+                Severity::Warning,
+                format!("primary group of {} changed to gid 0", name),
+            ));
+        }
+        if field(was, 0) != field(now, 0) {
+            findings.push(match field(now, 0) {
+                "" => Finding::new(Severity::Critical, format!("{} has no password", name)),
+                _ => Finding::new(
+                    Severity::Warning,
+                    format!("password field of {} changed in passwd", name),
+                ),
+            });
+        }
+        if field(was, 5) != shell {
+            let severity = if !can_login(field(was, 5)) && can_login(shell) {
+                Severity::Warning
+            } else {
+                Severity::Info
+            };
+            findings.push(Finding::new(
+                severity,
+                format!("shell of {} changed to {}", name, shell),
+            ));
+        }
+        if field(was, 4) != field(now, 4) {
+            findings.push(Finding::new(
+                Severity::Info,
+                format!("home of {} changed to {}", name, field(now, 4)),
+            ));
+        }
+    }
+    for (name, was) in &old {
+        if !new.contains_key(name) {
+            let severity = if is_root_id(field(was, 1)) {
+                Severity::Warning
+            } else {
+                Severity::Info
+            };
+            findings.push(Finding::new(severity, format!("user {} removed", name)));
+        }
+    }
+    findings
+}
+
+fn locked(hash: &str) -> bool {
+    hash.starts_with('!') || hash.starts_with('*')
+}
+
+pub fn shadow(old: &str, new: &str) -> Vec<Finding> {
+    // fields after the name: password hash, then aging fields
+    let (old, new) = (records(old), records(new));
+    let mut findings = Vec::new();
+    for (name, now) in &new {
+        let hash = field(now, 0);
+        let Some(was) = old.get(name) else {
+            findings.push(if hash.is_empty() {
+                Finding::new(
+                    Severity::Critical,
+                    format!("{} added with an empty password", name),
+                )
+            } else {
+                Finding::new(Severity::Info, format!("{} added to shadow", name))
+            });
+            continue;
+        };
+        let old_hash = field(was, 0);
+        if old_hash == hash {
+            continue;
+        }
+        let root = *name == "root";
+        findings.push(if hash.is_empty() {
+            Finding::new(
+                Severity::Critical,
+                format!("{} now has an empty password", name),
+            )
+        } else if locked(old_hash) && !locked(hash) {
+            Finding::new(
+                if root {
+                    Severity::Critical
+                } else {
+                    Severity::Warning
+                },
+                format!("{} unlocked: password login enabled", name),
+            )
+        } else if !locked(old_hash) && locked(hash) {
+            Finding::new(Severity::Info, format!("{} locked", name))
+        } else {
+            Finding::new(
+                if root {
+                    Severity::Warning
+                } else {
+                    Severity::Info
+                },
+                format!("password of {} changed", name),
+            )
+        });
+    }
+    for name in old.keys().filter(|n| !new.contains_key(*n)) {
+        findings.push(Finding::new(
+            Severity::Info,
+            format!("{} removed from shadow", name),
+        ));
+    }
+    findings
+}
+
+fn group_severity(name: &str, gid: &str) -> Severity {
+    if is_root_id(gid) || ROOT_GROUPS.contains(&name) {
+        Severity::Critical
+    } else if SENSITIVE_GROUPS.contains(&name) {
+        Severity::Warning
+    } else {
+        Severity::Info
+    }
+}
+
+fn members<'a>(fields: &[&'a str]) -> Vec<&'a str> {
+    field(fields, 2)
+        .split(',')
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .collect()
+}
+
+pub fn group(old: &str, new: &str) -> Vec<Finding> {
+    // fields after the name: password, gid, members
+    let (old, new) = (records(old), records(new));
+    let mut findings = Vec::new();
+    for (name, now) in &new {
+        let gid = field(now, 1);
+        let was = old.get(name);
+        match was {
+            None if is_root_id(gid) => findings.push(Finding::new(
+                Severity::Critical,
+                format!("group {} added with gid {}", name, gid),
+            )),
+            None => findings.push(Finding::new(
+                Severity::Info,
+                format!("group {} added with gid {}", name, gid),
+            )),
+            Some(was) if field(was, 1) != gid => findings.push(Finding::new(
+                group_severity(name, gid).max(Severity::Warning),
+                format!(
+                    "gid of group {} changed from {} to {}",
+                    name,
+                    field(was, 1),
+                    gid
+                ),
+            )),
+            Some(_) => {}
+        }
+        let before = was.map(|w| members(w)).unwrap_or_default();
+        let after = members(now);
+        for member in after.iter().filter(|m| !before.contains(m)) {
+            findings.push(Finding::new(
+                group_severity(name, gid),
+                format!("{} added to group {}", member, name),
+            ));
+        }
+        for member in before.iter().filter(|m| !after.contains(m)) {
+            findings.push(Finding::new(
+                Severity::Info,
+                format!("{} removed from group {}", member, name),
+            ));
+        }
+    }
+    for name in old.keys().filter(|n| !new.contains_key(*n)) {
+        findings.push(Finding::new(
+            Severity::Info,
+            format!("group {} removed", name),
+        ));
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{group, passwd, shadow};
+    use crate::integrity::semantic::Severity;
+
+    #[test]
+    fn passwd_changes() {
+        let old = "root:x:0:0:root:/root:/bin/bash\n\
+                   daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin\n\
+                   alice:x:1000:1000::/home/alice:/bin/bash\n";
+        let new = "root:x:0:0:root:/root:/bin/bash\n\
+                   daemon:x:1:1:daemon:/usr/sbin:/bin/bash\n\
+                   alice:x:0:1000::/home/alice:/bin/bash\n\
+                   mallory:x:1001:1001::/home/mallory:/bin/sh\n";
+        let found: Vec<_> = passwd(old, new)
+            .into_iter()
+            .map(|f| (f.severity, f.message))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    Severity::Critical,
+                    "uid of alice changed from 1000 to 0".into()
+                ),
+                (
+                    Severity::Warning,
+                    "shell of daemon changed to /bin/bash".into()
+                ),
+                (
+                    Severity::Warning,
+                    "user mallory added with uid 1001 and login shell /bin/sh".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn shadow_changes() {
+        let old = "root:$6$abc:19000:0:99999:7:::\nbin:*:19000:0:99999:7:::\nbob:$6$x:1::::::\n";
+        let new = "root::19000:0:99999:7:::\nbin:$6$new:19000:0:99999:7:::\nbob:$6$y:1::::::\n";
+        let messages: Vec<_> = shadow(old, new).into_iter().map(|f| f.message).collect();
+        assert_eq!(
+            messages,
+            [
+                "bin unlocked: password login enabled",
+                "password of bob changed",
+                "root now has an empty password",
+            ]
+        );
+    }
+
+    #[test]
+    fn root_ids_are_compared_numerically() {
+        let old = "root:x:0:0:root:/root:/bin/bash\n";
+        let new = "root:x:0:0:root:/root:/bin/bash\ntoor:x:00:000::/root:/bin/bash\n";
+        let found = passwd(old, new);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].severity, Severity::Critical);
+        assert_eq!(found[0].message, "user toor added with uid 00");
+
+        let found = group("", "wheel2:x:00:\n");
+        assert_eq!(found[0].severity, Severity::Critical);
+        assert_eq!(found[0].message, "group wheel2 added with gid 00");
+    }
+
+    #[test]
+    fn group_membership() {
+        let old = "sudo:x:27:alice\nusers:x:100:\n";
+        let new = "sudo:x:27:alice,mallory\nusers:x:100:mallory\n";
+        let found = group(old, new);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].severity, Severity::Critical);
+        assert_eq!(found[0].message, "mallory added to group sudo");
+        assert_eq!(found[1].severity, Severity::Info);
+    }
+}
@@ -0,0 +1,127 @@
+// ~/.ssh/authorized_keys
+use super::{Finding, Severity};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// A key line: options (possibly empty), key type and comment, by key blob.
+type Keys<'a> = BTreeMap<&'a str, (String, &'a str, String)>;
+
+fn is_key_type(token: &str) -> bool {
+    token.starts_with("ssh-") || token.starts_with("ecdsa-") || token.starts_with("sk-")
+}
+
+fn keys(text: &str) -> Keys<'_> {
+    let mut keys = BTreeMap::new();
+    for line in super::lines(text) {
+        let tokens: Vec<_> = line.split_whitespace().collect();
+        let Some(i) = tokens.iter().position(|t| is_key_type(t)) else {
+            continue;
+        };
+        let Some(blob) = tokens.get(i + 1) else {
+            continue;
+        };
+        let options = tokens[..i].join(" ");
+        let comment = tokens[i + 2..].join(" ");
+        keys.insert(*blob, (options, tokens[i], comment));
+    }
+    keys
+}
+
+/// The account whose keys `path` holds: the owner of the home directory the
+/// `.ssh` directory is in.
+fn account(path: &str) -> String {
+    let path = Path::new(path);
+    let home = match path.parent() {
+        Some(dir) if dir.file_name().is_some_and(|n| n == ".ssh") => dir.parent(),
+        dir => dir,
+    };
+    home.and_then(Path::file_name)
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+fn describe(key_type: &str, comment: &str) -> String {
+    if comment.is_empty() {
+        key_type.to_string()
+    } else {
+        format!("{} {}", key_type, comment)
+    }
+}
+
+pub fn analyze(path: &str, old: &str, new: &str) -> Vec<Finding> {
+    let user = account(path);
+    let (old, new) = (keys(old), keys(new));
+    let mut findings = Vec::new();
+    for (blob, (options, key_type, comment)) in &new {
+        let key = describe(key_type, comment);
+        match old.get(blob) {
+            None => {
+                let severity = if user == "root" {
+                    Severity::Critical
+                } else {
+                    Severity::Warning
+                };
+                let message = if options.is_empty() {
+                    format!("SSH key added for {}: {}", user, key)
+                } else {
+                    format!("SSH key added for {}: {} ({})", user, key, options)
+                };
+                findings.push(Finding::new(severity, message));
+            }
+            Some((was, _, _)) if was != options => findings.push(Finding::new(
+                Severity::Warning,
+                format!(
+                    "options of SSH key {} for {} changed to {:?}",
+                    key, user, options
+                ),
+            )),
+            Some(_) => {}
+        }
+    }
+    for (blob, (_, key_type, comment)) in &old {
+        if !new.contains_key(blob) {
+            findings.push(Finding::new(
+                Severity::Info,
+                format!(
+                    "SSH key removed for {}: {}",
+                    user,
+                    describe(key_type, comment)
+                ),
+            ));
+        }
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::analyze;
+    use crate::integrity::semantic::Severity;
+
+    #[test]
+    fn added_and_removed_keys() {
+        let old = "ssh-ed25519 AAAAC3old admin@laptop\n";
+        let new = "# deploy\nfrom=\"10.0.0.0/8\" ssh-ed25519 AAAAC3new ci\nssh-rsa AAAAB3x\n";
+        let found: Vec<_> = analyze("/root/.ssh/authorized_keys", old, new)
+            .into_iter()
+            .map(|f| (f.severity, f.message))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (Severity::Critical, "SSH key added for root: ssh-rsa".into()),
+                (
+                    Severity::Critical,
+                    "SSH key added for root: ssh-ed25519 ci (from=\"10.0.0.0/8\")".into()
+                ),
+                (
+                    Severity::Info,
+                    "SSH key removed for root: ssh-ed25519 admin@laptop".into()
+                ),
+            ]
+        );
+        let found = analyze("/home/alice/.ssh/authorized_keys", "", new);
+        assert_eq!(found[0].severity, Severity::Warning);
+        assert!(found[0].message.contains("for alice"));
+    }
+}
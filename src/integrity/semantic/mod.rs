@@ -0,0 +1,210 @@
+//! Structured findings for changes to well-known security-relevant files
+//! (`semantic_findings`): accounts, sudoers, sshd_config, crontabs and authorized_keys.
+
+mod accounts;
+mod authorized_keys;
+mod cron;
+mod sshd;
+mod sudoers;
+
+use crate::integrity::store::ContentStore;
+use crate::integrity::{Change, ChangeKind, Entry};
+use anyhow::Result;
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+
+/// Files larger than this are not parsed.
+const MAX_PARSED_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// One security-relevant fact about a change, e.g. "user x added with uid 0".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Finding {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Finding {
+            severity,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// The kinds of files findings are produced for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KnownFile {
+    Passwd,
+    Shadow,
+    Group,
+    Sudoers,
+    SshdConfig,
+    /// `/etc/crontab` and `/etc/cron.d/*`, with a user field
+    SystemCrontab,
+    /// `/var/spool/cron/…/<user>`
+    UserCrontab,
+    AuthorizedKeys,
+}
+
+impl KnownFile {
+    /// Recognize `path` by its name and directory.
+    pub fn of(path: &str) -> Option<Self> {
+        let path = Path::new(path);
+        let name = path.file_name()?.to_str()?;
+        let dir = path
+            .parent()
+            .and_then(Path::file_name)
+            .and_then(|d| d.to_str())
+            .unwrap_or_default();
+        let in_spool = path
+            .to_str()
+            .is_some_and(|p| p.starts_with("/var/spool/cron/"));
+        Some(match (dir, name) {
+            (_, "passwd") => KnownFile::Passwd,
+            (_, "shadow") => KnownFile::Shadow,
+            (_, "group") => KnownFile::Group,
+            (_, "sudoers") | ("sudoers.d", _) => KnownFile::Sudoers,
+            (_, "sshd_config") | ("sshd_config.d", _) => KnownFile::SshdConfig,
+            (_, "crontab") | ("cron.d", _) => KnownFile::SystemCrontab,
+            _ if in_spool => KnownFile::UserCrontab,
+            (_, "authorized_keys" | "authorized_keys2") => KnownFile::AuthorizedKeys,
+            _ => return None,
+        })
+    }
+}
+
+/// Findings for `path` changing from `old` to `new` content (empty for a file that
+/// did not or no longer exists), most severe first.
+pub fn analyze(file: KnownFile, path: &str, old: &str, new: &str) -> Vec<Finding> {
+    let mut findings = match file {
+        KnownFile::Passwd => accounts::passwd(old, new),
+        KnownFile::Shadow => accounts::shadow(old, new),
+        KnownFile::Group => accounts::group(old, new),
+        KnownFile::Sudoers => sudoers::analyze(old, new),
+        KnownFile::SshdConfig => sshd::analyze(old, new),
+        KnownFile::SystemCrontab => cron::analyze(old, new, None),
+        KnownFile::UserCrontab => {
+            let user = Path::new(path).file_name().and_then(|n| n.to_str());
+            cron::analyze(old, new, user)
+        }
+        KnownFile::AuthorizedKeys => authorized_keys::analyze(path, old, new),
+    };
+    findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    findings
+}
+
+/// Findings for a created, modified, replaced or deleted known file, reading both versions
+/// from `store`. Empty if the file is not known or a version is not stored.
+pub fn for_change(store: &ContentStore, change: &Change) -> Result<Vec<Finding>> {
+    let Some(file) = KnownFile::of(&change.path) else {
+        return Ok(Vec::new());
+    };
+    if !matches!(
+        change.kind,
+        ChangeKind::Created | ChangeKind::Modified | ChangeKind::Replaced | ChangeKind::Deleted
+    ) {
+        return Ok(Vec::new());
+    }
+    if let (Some(old), Some(new)) = (&change.old, &change.new)
+        && old.same_content(new)
+    {
+        return Ok(Vec::new());
+    }
+    let content = |entry: Option<&Entry>| -> Result<Option<String>> {
+        let Some(entry) = entry else {
+            return Ok(Some(String::new()));
+        };
+        if entry.size.is_some_and(|size| size > MAX_PARSED_SIZE) {
+            return Ok(None);
+        }
+        Ok(store
+            .get_entry(entry)?
+            .map(|data| String::from_utf8_lossy(&data).into_owned()))
+    };
+    let (Some(old), Some(new)) = (content(change.old.as_ref())?, content(change.new.as_ref())?)
+    else {
+        return Ok(Vec::new());
+    };
+    Ok(analyze(file, &change.path, &old, &new))
+}
+
+/// Non-empty lines that are not `#` comments, trimmed.
+fn lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KnownFile, Severity, analyze};
+
+    #[test]
+    fn known_files_are_recognized() {
+        let of = KnownFile::of;
+        assert_eq!(of("/etc/passwd"), Some(KnownFile::Passwd));
+        assert_eq!(of("/etc/sudoers.d/90-cloud"), Some(KnownFile::Sudoers));
+        assert_eq!(
+            of("/etc/ssh/sshd_config.d/50-local.conf"),
+            Some(KnownFile::SshdConfig)
+        );
+        assert_eq!(of("/etc/cron.d/backup"), Some(KnownFile::SystemCrontab));
+        assert_eq!(
+            of("/var/spool/cron/crontabs/www-data"),
+            Some(KnownFile::UserCrontab)
+        );
+        assert_eq!(
+            of("/home/alice/.ssh/authorized_keys"),
+            Some(KnownFile::AuthorizedKeys)
+        );
+        assert_eq!(of("/etc/hosts"), None);
+    }
+
+    #[test]
+    fn findings_are_sorted_by_severity() {
+        let old = "root:x:0:0:root:/root:/bin/bash\n";
+        let new = "root:x:0:0:root:/root:/bin/bash\n\
+                   backup:x:34:34:backup:/var/backups:/usr/sbin/nologin\n\
+                   toor:x:0:0::/root:/bin/sh\n";
+        let findings = analyze(KnownFile::Passwd, "/etc/passwd", old, new);
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].severity, Severity::Critical);
+        assert_eq!(findings[0].message, "user toor added with uid 0");
+        assert_eq!(
+            findings[1].to_string(),
+            "info: user backup added with uid 34"
+        );
+    }
+}
@@ -0,0 +1,134 @@
+// /etc/ssh/sshd_config and sshd_config.d/*
+use super::{Finding, Severity};
+use std::collections::BTreeMap;
+
+/// How risky `value` is for the (lowercase) option `key`.
+fn risk(key: &str, value: &str) -> Severity {
+    let value = value.to_ascii_lowercase();
+    match key {
+        "permitrootlogin" => match value.as_str() {
+            "yes" => Severity::Critical,
+            "no" => Severity::Info,
+            _ => Severity::Warning,
+        },
+        "permitemptypasswords" if value == "yes" => Severity::Critical,
+        "passwordauthentication"
+        | "kbdinteractiveauthentication"
+        | "challengeresponseauthentication"
+        | "permituserenvironment"
+        | "gatewayports"
+        | "permittunnel"
+            if value == "yes" =>
+        {
+            Severity::Warning
+        }
+        "strictmodes" if value == "no" => Severity::Warning,
+        "authorizedkeysfile"
+        | "authorizedkeyscommand"
+        | "authorizedkeyscommanduser"
+        | "authorizedprincipalsfile"
+        | "trustedusercakeys"
+        | "allowusers"
+        | "allowgroups"
+        | "denyusers"
+        | "denygroups"
+        | "include" => Severity::Warning,
+        _ => Severity::Info,
+    }
+}
+
+/// Keyword as written and value, by lowercase keyword.
+type Options<'a> = BTreeMap<String, (&'a str, &'a str)>;
+
+/// Options outside `Match` blocks, with the first value of each (sshd uses the
+/// first one it reads), and the lines of all `Match` blocks.
+fn options(text: &str) -> (Options<'_>, Vec<&str>) {
+    let mut options = BTreeMap::new();
+    let mut matches = Vec::new();
+    for line in super::lines(text) {
+        let (key, value) = line
+            .split_once(|c: char| c.is_whitespace() || c == '=')
+            .map(|(k, v)| {
+                (
+                    k,
+                    v.trim_start_matches(|c: char| c.is_whitespace() || c == '='),
+                )
+            })
+            .unwrap_or((line, ""));
+        if !matches.is_empty() || key.eq_ignore_ascii_case("match") {
+            matches.push(line);
+            continue;
+        }
+        options
+            .entry(key.to_ascii_lowercase())
+            .or_insert((key, value.trim()));
+    }
+    (options, matches)
+}
+
+pub fn analyze(old: &str, new: &str) -> Vec<Finding> {
+    let ((old, old_matches), (new, new_matches)) = (options(old), options(new));
+    let mut findings = Vec::new();
+    for (key, (name, value)) in &new {
+        match old.get(key) {
+            None => findings.push(Finding::new(
+                risk(key, value),
+                format!("{} set to {}", name, value),
+            )),
+            Some((_, was)) if was != value => findings.push(Finding::new(
+                risk(key, value),
+                format!("{} changed to {}", name, value),
+            )),
+            Some(_) => {}
+        }
+    }
+    for (key, (name, _)) in old.iter().filter(|(k, _)| !new.contains_key(*k)) {
+        // Dropping a hardening option re-enables the default
+        let severity = risk(key, "yes").min(Severity::Warning);
+        findings.push(Finding::new(
+            severity,
+            format!("{} removed, default applies", name),
+        ));
+    }
+    if old_matches != new_matches {
+        findings.push(Finding::new(Severity::Info, "Match blocks changed"));
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::analyze;
+    use crate::integrity::semantic::Severity;
+
+    #[test]
+    fn risky_options_are_flagged() {
+        let old = "Port 22\nPermitRootLogin no\nPasswordAuthentication no\n";
+        let new = "Port 2222\nPermitRootLogin yes\nPermitRootLogin no\nX11Forwarding yes\n";
+        let found: Vec<_> = analyze(old, new)
+            .into_iter()
+            .map(|f| (f.severity, f.message))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (Severity::Critical, "PermitRootLogin changed to yes".into()),
+                (Severity::Info, "Port changed to 2222".into()),
+                (Severity::Info, "X11Forwarding set to yes".into()),
+                (
+                    Severity::Warning,
+                    "PasswordAuthentication removed, default applies".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn match_blocks_do_not_override_global_options() {
+        let old = "PermitRootLogin no\n";
+        let new = "PermitRootLogin no\nMatch Address 10.0.0.0/8\n    PermitRootLogin yes\n";
+        let found = analyze(old, new);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].message, "Match blocks changed");
+    }
+}
@@ -0,0 +1,109 @@
+// /etc/sudoers and /etc/sudoers.d/*
+use super::{Finding, Severity};
+use std::collections::BTreeSet;
+
+const ALIASES: &[&str] = &["User_Alias", "Runas_Alias", "Host_Alias", "Cmnd_Alias"];
+
+/// Entries with continuation lines joined and whitespace collapsed. `#include`
+/// and `#<uid>` lines are entries, other `#` lines comments.
+fn entries(text: &str) -> BTreeSet<String> {
+    let mut entries = BTreeSet::new();
+    let mut current = String::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if current.is_empty() {
+            let is_entry = !line.starts_with('#')
+                || line.starts_with("#include")
+                || line[1..].starts_with(|c: char| c.is_ascii_digit());
+            if line.is_empty() || !is_entry {
+                continue;
+            }
+        }
+        match line.strip_suffix('\\') {
+            Some(part) => {
+                current.push_str(part);
+                current.push(' ');
+            }
+            None => {
+                current.push_str(line);
+                entries.insert(current.split_whitespace().collect::<Vec<_>>().join(" "));
+                current.clear();
+            }
+        }
+    }
+    if !current.trim().is_empty() {
+        entries.insert(current.split_whitespace().collect::<Vec<_>>().join(" "));
+    }
+    entries
+}
+
+fn added(entry: &str) -> Finding {
+    let first = entry.split_whitespace().next().unwrap_or_default();
+    if first.starts_with("#include") || first.starts_with("@include") {
+        return Finding::new(
+            Severity::Warning,
+            format!("sudoers now includes: {}", entry),
+        );
+    }
+    if first.starts_with("Defaults") {
+        let severity = if entry.contains("!authenticate") {
+            Severity::Critical
+        } else if entry.contains("env_keep") || entry.contains("!env_reset") {
+            Severity::Warning
+        } else {
+            Severity::Info
+        };
+        return Finding::new(severity, format!("sudoers default added: {}", entry));
+    }
+    if ALIASES.contains(&first) {
+        return Finding::new(Severity::Warning, format!("sudoers alias added: {}", entry));
+    }
+    if entry.contains("NOPASSWD") {
+        Finding::new(
+            Severity::Critical,
+            format!("sudoers grants NOPASSWD to {}: {}", first, entry),
+        )
+    } else {
+        Finding::new(
+            Severity::Warning,
+            format!("sudoers grants {}: {}", first, entry),
+        )
+    }
+}
+
+pub fn analyze(old: &str, new: &str) -> Vec<Finding> {
+    let (old, new) = (entries(old), entries(new));
+    let mut findings: Vec<_> = new.difference(&old).map(|e| added(e)).collect();
+    findings.extend(
+        old.difference(&new)
+            .map(|e| Finding::new(Severity::Info, format!("sudoers entry removed: {}", e))),
+    );
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::analyze;
+    use crate::integrity::semantic::Severity;
+
+    #[test]
+    fn new_rules_are_reported() {
+        let old =
+            "# User privilege specification\nroot ALL=(ALL:ALL) ALL\n%sudo ALL=(ALL:ALL) ALL\n";
+        let new = "root    ALL=(ALL:ALL) ALL\n%sudo ALL=(ALL:ALL) ALL\n\
+                   deploy ALL=(ALL) NOPASSWD: \\\n    /usr/bin/systemctl\n\
+                   #includedir /etc/sudoers.d\n";
+        let found = analyze(old, new);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].severity, Severity::Warning);
+        assert_eq!(
+            found[0].message,
+            "sudoers now includes: #includedir /etc/sudoers.d"
+        );
+        assert_eq!(found[1].severity, Severity::Critical);
+        assert_eq!(
+            found[1].message,
+            "sudoers grants NOPASSWD to deploy: deploy ALL=(ALL) NOPASSWD: /usr/bin/systemctl"
+        );
+    }
+}
@@ -0,0 +1,264 @@
+// Baseline signatures (Ed25519 or HMAC-SHA256)
+use crate::config::{Config, SigningConfig, SigningMethod};
+use crate::utils::{from_hex, to_hex};
+use anyhow::{Context, Result};
+use ed25519_dalek::{Signer as _, SigningKey, Verifier as _, VerifyingKey};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+/// HMAC keys shorter than this are refused.
+const MIN_HMAC_KEY_LEN: usize = 16;
+
+/// A signature stored alongside the header and entries of a baseline file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Signature {
+    pub method: SigningMethod,
+    /// Hex signature (Ed25519) or MAC (HMAC-SHA256)
+    pub value: String,
+}
+
+/// Key material loaded from a `signing` configuration.
+#[derive(Clone)]
+pub enum Keys {
+    Ed25519 {
+        /// Absent on hosts that only verify
+        signing: Option<Box<SigningKey>>,
+        verifying: VerifyingKey,
+    },
+    Hmac(Vec<u8>),
+}
+
+// Never print key material
+impl fmt::Debug for Keys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Keys({})", self.method().as_str())
+    }
+}
+
+fn read_hex_key(path: &str) -> Result<[u8; 32]> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading key {}", path))?;
+    from_hex(text.trim())
+        .and_then(|b| <[u8; 32]>::try_from(b).ok())
+        .with_context(|| format!("{} does not hold a 32-byte hex key", path))
+}
+
+impl Keys {
+    /// The keys of `cfg.signing`, or `None` if baselines are not signed.
+    pub fn for_config(cfg: &Config) -> Result<Option<Self>> {
+        cfg.signing
+            .as_ref()
+            .map(|s| Keys::load(s).context("loading baseline signing keys"))
+            .transpose()
+    }
+
+    pub fn load(cfg: &SigningConfig) -> Result<Self> {
+        match cfg.method {
+            SigningMethod::Ed25519 => {
+                let signing = match &cfg.key_path {
+                    Some(p) => Some(Box::new(SigningKey::from_bytes(&read_hex_key(p)?))),
+                    None => None,
+                };
+                let verifying = match (&cfg.public_key_path, &signing) {
+                    (Some(p), _) => VerifyingKey::from_bytes(&read_hex_key(p)?)
+                        .with_context(|| format!("{} is not a valid Ed25519 public key", p))?,
+                    (None, Some(k)) => k.verifying_key(),
+                    (None, None) => {
+                        anyhow::bail!("signing: ed25519 needs key_path or public_key_path")
+                    }
+                };
+                if let Some(k) = &signing
+                    && k.verifying_key() != verifying
+                {
+                    anyhow::bail!("signing: key_path and public_key_path are not a key pair");
+                }
+                Ok(Keys::Ed25519 { signing, verifying })
+            }
+            SigningMethod::HmacSha256 => {
+                let path = cfg
+                    .key_path
+                    .as_deref()
+                    .context("signing: hmac-sha256 needs key_path")?;
+                let key = fs::read(path).with_context(|| format!("reading key {}", path))?;
+                let key = key.trim_ascii().to_vec();
+                if key.len() < MIN_HMAC_KEY_LEN {
+                    anyhow::bail!(
+                        "HMAC key {} is shorter than {} bytes",
+                        path,
+                        MIN_HMAC_KEY_LEN
+                    );
+                }
+                Ok(Keys::Hmac(key))
+            }
+        }
+    }
+
+    pub fn method(&self) -> SigningMethod {
+        match self {
+            Keys::Ed25519 { .. } => SigningMethod::Ed25519,
+            Keys::Hmac(_) => SigningMethod::HmacSha256,
+        }
+    }
+
+    fn mac(key: &[u8]) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length")
+    }
+
+    /// Whether these keys can sign, not only verify.
+    pub fn can_sign(&self) -> bool {
+        match self {
+            Keys::Ed25519 { signing, .. } => signing.is_some(),
+            Keys::Hmac(_) => true,
+        }
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Result<Signature> {
+        let value = match self {
+            Keys::Ed25519 { signing, .. } => {
+                let key = signing
+                    .as_ref()
+                    .context("no Ed25519 private key configured (key_path), cannot sign")?;
+                to_hex(&key.sign(message).to_bytes())
+            }
+            Keys::Hmac(key) => {
+                let mut mac = Self::mac(key);
+                mac.update(message);
+                to_hex(&mac.finalize().into_bytes())
+            }
+        };
+        Ok(Signature {
+            method: self.method(),
+            value,
+        })
+    }
+
+    /// Check `signature` over `message`. A missing signature, one made with
+    /// another method, or one that does not match are all errors.
+    pub fn verify(&self, message: &[u8], signature: Option<&Signature>) -> Result<()> {
+        let sig = signature.context("baseline is not signed")?;
+        if sig.method != self.method() {
+            anyhow::bail!(
+                "baseline is signed with {}, expected {}",
+                sig.method.as_str(),
+                self.method().as_str()
+            );
+        }
+        let bytes = from_hex(&sig.value).context("signature is not valid hex")?;
+        let valid = match self {
+            Keys::Ed25519 { verifying, .. } => ed25519_dalek::Signature::from_slice(&bytes)
+                .map(|s| verifying.verify(message, &s).is_ok())
+                .unwrap_or(false),
+            Keys::Hmac(key) => {
+                let mut mac = Self::mac(key);
+                mac.update(message);
+                mac.verify_slice(&bytes).is_ok()
+            }
+        };
+        if !valid {
+            anyhow::bail!("baseline signature does not match its content");
+        }
+        Ok(())
+    }
+}
+
+/// Create a new key at `out` (mode 0600) for `method`, plus `out.pub` for
+/// Ed25519. Existing files are never overwritten. Returns the files written.
+pub fn generate_key(method: SigningMethod, out: &Path) -> Result<Vec<PathBuf>> {
+    let mut secret = [0u8; 32];
+    fs::File::open("/dev/urandom")
+        .and_then(|mut f| f.read_exact(&mut secret))
+        .context("reading /dev/urandom")?;
+
+    let write_new = |path: &Path, contents: &str, mode: u32| -> Result<()> {
+        let mut f = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode)
+            .open(path)
+            .with_context(|| format!("creating {:?}", path))?;
+        writeln!(f, "{}", contents).with_context(|| format!("writing {:?}", path))
+    };
+
+    write_new(out, &to_hex(&secret), 0o600)?;
+    let mut written = vec![out.to_path_buf()];
+    if method == SigningMethod::Ed25519 {
+        let public = SigningKey::from_bytes(&secret).verifying_key();
+        let mut pub_path = out.as_os_str().to_owned();
+        pub_path.push(".pub");
+        let pub_path = PathBuf::from(pub_path);
+        write_new(&pub_path, &to_hex(public.as_bytes()), 0o644)?;
+        written.push(pub_path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Keys, generate_key};
+    use crate::config::{SigningConfig, SigningMethod};
+    use tempfile::tempdir;
+
+    fn keys(method: SigningMethod, key: Option<String>, public: Option<String>) -> Keys {
+        Keys::load(&SigningConfig {
+            method,
+            key_path: key,
+            public_key_path: public,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn ed25519_sign_and_verify_with_public_key_only() {
+        let dir = tempdir().unwrap();
+        let key = dir.path().join("baseline.key");
+        let files = generate_key(SigningMethod::Ed25519, &key).unwrap();
+        assert_eq!(files.len(), 2);
+        let path = |p: &std::path::Path| Some(p.to_str().unwrap().to_string());
+
+        let signer = keys(SigningMethod::Ed25519, path(&key), None);
+        let sig = signer.sign(b"entries").unwrap();
+
+        let verifier = keys(SigningMethod::Ed25519, None, path(&files[1]));
+        verifier.verify(b"entries", Some(&sig)).unwrap();
+        assert!(verifier.verify(b"tampered", Some(&sig)).is_err());
+        assert!(verifier.verify(b"entries", None).is_err());
+        assert!(verifier.sign(b"entries").is_err());
+    }
+
+    #[test]
+    fn hmac_rejects_other_key() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a.key");
+        let b = dir.path().join("b.key");
+        generate_key(SigningMethod::HmacSha256, &a).unwrap();
+        generate_key(SigningMethod::HmacSha256, &b).unwrap();
+        let ka = keys(
+            SigningMethod::HmacSha256,
+            Some(a.to_str().unwrap().into()),
+            None,
+        );
+        let kb = keys(
+            SigningMethod::HmacSha256,
+            Some(b.to_str().unwrap().into()),
+            None,
+        );
+
+        let sig = ka.sign(b"entries").unwrap();
+        ka.verify(b"entries", Some(&sig)).unwrap();
+        let err = kb.verify(b"entries", Some(&sig)).unwrap_err();
+        assert!(err.to_string().contains("does not match"));
+    }
+
+    #[test]
+    fn keygen_never_overwrites() {
+        let dir = tempdir().unwrap();
+        let key = dir.path().join("k");
+        generate_key(SigningMethod::HmacSha256, &key).unwrap();
+        assert!(generate_key(SigningMethod::HmacSha256, &key).is_err());
+    }
+}
@@ -0,0 +1,382 @@
+// Content-addressed store of compressed file contents (`store_contents`)
+use crate::config::{Config, ContentStoreConfig, HashAlgorithm, JobConfig};
+use crate::integrity::{
+    Baseline, Entry, FileKind, baseline_path, hash_reader, read_baseline_unverified,
+};
+use anyhow::{Context, Result};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::collections::BTreeSet;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+/// A stored content, by the digest it was recorded under.
+pub type Key = (HashAlgorithm, String);
+
+/// Copies of file contents under `<path>/objects/<alg>/<xx>/<rest of hex>.gz`. Objects
+/// are only written when the file still matches the digest they are filed under, so
+/// a copy can be trusted to be exactly what the baseline recorded.
+#[derive(Debug, Clone)]
+pub struct ContentStore {
+    cfg: ContentStoreConfig,
+}
+
+/// What a `prune` removed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Pruned {
+    pub objects: usize,
+    pub bytes: u64,
+}
+
+/// Forwards everything read from `reader` to `writer` as well.
+struct Tee<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: Read, W: Write> Read for Tee<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.writer.write_all(&buf[..n])?;
+        Ok(n)
+    }
+}
+
+fn valid_hex(hex: &str) -> bool {
+    hex.len() > 2
+        && hex
+            .bytes()
+            .all(|b| b.is_ascii_hexdigit() && !b.is_ascii_uppercase())
+}
+
+impl ContentStore {
+    pub fn new(cfg: ContentStoreConfig) -> Self {
+        ContentStore { cfg }
+    }
+
+    /// The store of `cfg` if `job_cfg` keeps contents (defaults apply when the
+    /// config has no `content_store` section).
+    pub fn for_job(cfg: &Config, job_cfg: &JobConfig) -> Option<Self> {
+        job_cfg
+            .keeps_contents()
+            .then(|| ContentStore::new(cfg.content_store.clone().unwrap_or_default()))
+    }
+
+    fn objects(&self) -> PathBuf {
+        Path::new(&self.cfg.path).join("objects")
+    }
+
+    fn object_path(&self, alg: HashAlgorithm, hex: &str) -> PathBuf {
+        self.objects()
+            .join(alg.as_str())
+            .join(&hex[..2])
+            .join(format!("{}.gz", &hex[2..]))
+    }
+
+    /// Store the content of `path`, recorded as `entry`. Returns whether a new object
+    /// was written; directories, symlinks, partially hashed or oversized files, and
+    /// files that no longer match `entry`, are skipped. Re-storing a known content
+    /// only refreshes its age.
+    pub fn put(&self, path: &str, entry: &Entry) -> Result<bool> {
+        let regular = entry.file_type.is_none_or(|t| t == FileKind::File);
+        let too_big = entry.size.is_some_and(|s| s > self.cfg.max_file_size);
+        let Some((&alg, hex)) = entry.hashes.iter().next() else {
+            return Ok(false);
+        };
+        if !regular || too_big || !entry.hashed.is_full() || !valid_hex(hex) {
+            return Ok(false);
+        }
+        let object = self.object_path(alg, hex);
+        if object.exists() {
+            OpenOptions::new()
+                .append(true)
+                .open(&object)
+                .and_then(|f| f.set_modified(SystemTime::now()))
+                .with_context(|| format!("touching {:?}", object))?;
+            return Ok(false);
+        }
+
+        let dir = object.parent().expect("object paths have a parent");
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .with_context(|| format!("creating {:?}", dir))?;
+        // Random, so job threads storing the same content at once do not collide
+        let tmp = dir.join(format!(".{}.{:016x}.tmp", &hex[2..], fastrand::u64(..)));
+        let out = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&tmp)
+            .with_context(|| format!("creating {:?}", tmp))?;
+
+        let result = (|| -> Result<bool> {
+            let file = File::open(path).with_context(|| format!("opening {}", path))?;
+            let mut tee = Tee {
+                reader: file.take(self.cfg.max_file_size + 1),
+                writer: GzEncoder::new(out, Compression::default()),
+            };
+            let digest = hash_reader(&mut tee, alg).with_context(|| format!("reading {}", path))?;
+            let out = tee.writer.finish()?;
+            if digest != *hex {
+                // Changed since it was recorded; this is not the content we want
+                return Ok(false);
+            }
+            out.sync_all()?;
+            fs::rename(&tmp, &object).with_context(|| format!("storing {:?}", object))?;
+            Ok(true)
+        })();
+        if !matches!(result, Ok(true)) {
+            let _ = fs::remove_file(&tmp);
+        }
+        result
+    }
+
+    /// Store every entry of `baseline`, reporting failures without stopping.
+    /// Returns the number of new objects.
+    pub fn put_all(&self, baseline: &Baseline) -> usize {
+        let mut stored = 0;
+        for (path, entry) in baseline {
+            match self.put(path, entry) {
+                Ok(true) => stored += 1,
+                Ok(false) => {}
+                Err(e) => eprintln!("Failed to store content of {}: {:?}", path, e),
+            }
+        }
+        stored
+    }
+
+    /// The content filed under `alg`/`hex`, checked against that digest, or `None`
+    /// if it was never stored (or has been pruned).
+    pub fn get(&self, alg: HashAlgorithm, hex: &str) -> Result<Option<Vec<u8>>> {
+        if !valid_hex(hex) {
+            anyhow::bail!("`{}` is not a hex digest", hex);
+        }
+        let object = self.object_path(alg, hex);
+        let file = match File::open(&object) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("opening {:?}", object)),
+        };
+        let mut data = Vec::new();
+        GzDecoder::new(file)
+            .read_to_end(&mut data)
+            .with_context(|| format!("decompressing {:?}", object))?;
+        if hash_reader(&data[..], alg)? != hex {
+            anyhow::bail!("stored copy {:?} does not match its digest", object);
+        }
+        Ok(Some(data))
+    }
+
+    /// The stored content of `entry`, under any of its digests.
+    pub fn get_entry(&self, entry: &Entry) -> Result<Option<Vec<u8>>> {
+        if !entry.hashed.is_full() {
+            return Ok(None);
+        }
+        for (&alg, hex) in &entry.hashes {
+            if let Some(data) = self.get(alg, hex)? {
+                return Ok(Some(data));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The content of `hash`, given as `alg:hex` or as a bare hex digest of any algorithm.
+    pub fn find(&self, hash: &str) -> Result<Vec<u8>> {
+        let found = match hash.split_once(':') {
+            Some((alg, hex)) => {
+                let alg: HashAlgorithm = alg.parse().map_err(anyhow::Error::msg)?;
+                self.get(alg, hex)?
+            }
+            None => {
+                let mut found = None;
+                for alg in [
+                    HashAlgorithm::Sha256,
+                    HashAlgorithm::Sha512,
+                    HashAlgorithm::Blake3,
+                ] {
+                    found = self.get(alg, hash)?;
+                    if found.is_some() {
+                        break;
+                    }
+                }
+                found
+            }
+        };
+        found.with_context(|| format!("no stored content for {}", hash))
+    }
+
+    /// Apply `max_age_days` and `max_size` to the objects none of `referenced` names;
+    /// objects still referenced are never removed.
+    pub fn prune(&self, referenced: &BTreeSet<Key>) -> Result<Pruned> {
+        let objects = self.objects();
+        if !objects.exists() {
+            return Ok(Pruned::default());
+        }
+        let mut total = 0;
+        let mut candidates = Vec::new();
+        for dirent in WalkDir::new(&objects).min_depth(3).max_depth(3) {
+            let dirent = dirent.with_context(|| format!("walking {:?}", objects))?;
+            let path = dirent.path();
+            let meta = dirent.metadata()?;
+            total += meta.len();
+            let key = (|| {
+                let rest = path.file_name()?.to_str()?.strip_suffix(".gz")?;
+                let prefix = path.parent()?.file_name()?.to_str()?;
+                let alg = path.parent()?.parent()?.file_name()?.to_str()?;
+                Some((alg.parse().ok()?, format!("{}{}", prefix, rest)))
+            })();
+            if key.is_some_and(|k: Key| !referenced.contains(&k)) {
+                candidates.push((meta.modified()?, meta.len(), path.to_path_buf()));
+            }
+        }
+        candidates.sort();
+
+        let expired = self
+            .cfg
+            .max_age_days
+            .and_then(|d| SystemTime::now().checked_sub(Duration::from_secs(d * 86_400)));
+        let mut pruned = Pruned::default();
+        for (mtime, size, path) in candidates {
+            let too_old = expired.is_some_and(|e| mtime < e);
+            let too_big = self.cfg.max_size.is_some_and(|max| total > max);
+            if !too_old && !too_big {
+                continue;
+            }
+            fs::remove_file(&path).with_context(|| format!("removing {:?}", path))?;
+            total -= size;
+            pruned.objects += 1;
+            pruned.bytes += size;
+        }
+        Ok(pruned)
+    }
+}
+
+/// Every digest recorded in the current baselines of the jobs in `cfg`.
+pub fn referenced(cfg: &Config) -> BTreeSet<Key> {
+    let mut keys = BTreeSet::new();
+    for job_name in cfg.jobs.keys() {
+        let Ok(file) = read_baseline_unverified(&baseline_path(job_name)) else {
+            continue;
+        };
+        for entry in file.entries.values() {
+            keys.extend(entry.hashes.iter().map(|(alg, hex)| (*alg, hex.clone())));
+        }
+    }
+    keys
+}
+
+/// Prune the configured content store, if any job uses it.
+pub fn prune_config(cfg: &Config) -> Result<Pruned> {
+    if !cfg.jobs.values().any(JobConfig::keeps_contents) {
+        return Ok(Pruned::default());
+    }
+    ContentStore::new(cfg.content_store.clone().unwrap_or_default()).prune(&referenced(cfg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ContentStore, Key};
+    use crate::config::{ContentStoreConfig, HashAlgorithm};
+    use crate::integrity::{Entry, EntryOptions};
+    use std::collections::BTreeSet;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn store(dir: &std::path::Path) -> ContentStore {
+        ContentStore::new(ContentStoreConfig {
+            path: dir.join("store").to_str().unwrap().to_string(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn stores_once_and_reads_back() {
+        let dir = tempdir().unwrap();
+        let store = store(dir.path());
+        let a = dir.path().join("sudoers");
+        let b = dir.path().join("sudoers.bak");
+        fs::write(&a, "root ALL=(ALL) ALL\n").unwrap();
+        fs::write(&b, "root ALL=(ALL) ALL\n").unwrap();
+        let opts = EntryOptions::default();
+        let entry = Entry::from_path(a.to_str().unwrap(), &opts).unwrap();
+
+        assert!(store.put(a.to_str().unwrap(), &entry).unwrap());
+        assert!(!store.put(b.to_str().unwrap(), &entry).unwrap());
+        let data = store.get_entry(&entry).unwrap().unwrap();
+        assert_eq!(data, b"root ALL=(ALL) ALL\n");
+        let hex = entry.digest().unwrap();
+        assert_eq!(store.find(hex).unwrap(), data);
+        assert_eq!(store.find(&format!("sha256:{}", hex)).unwrap(), data);
+        assert!(store.find("../../etc").is_err());
+    }
+
+    #[test]
+    fn concurrent_puts_of_the_same_content_succeed() {
+        let dir = tempdir().unwrap();
+        let store = store(dir.path());
+        let file = dir.path().join("hosts");
+        fs::write(&file, "127.0.0.1 localhost\n").unwrap();
+        let path = file.to_str().unwrap();
+        let entry = Entry::from_path(path, &EntryOptions::default()).unwrap();
+
+        std::thread::scope(|s| {
+            let puts: Vec<_> = (0..8)
+                .map(|_| s.spawn(|| store.put(path, &entry)))
+                .collect();
+            for put in puts {
+                put.join().unwrap().unwrap();
+            }
+        });
+        assert_eq!(
+            store.get_entry(&entry).unwrap().unwrap(),
+            b"127.0.0.1 localhost\n"
+        );
+    }
+
+    #[test]
+    fn changed_file_is_not_stored_under_the_old_digest() {
+        let dir = tempdir().unwrap();
+        let store = store(dir.path());
+        let file = dir.path().join("authorized_keys");
+        fs::write(&file, "ssh-ed25519 AAAA admin").unwrap();
+        let path = file.to_str().unwrap();
+        let entry = Entry::from_path(path, &EntryOptions::default()).unwrap();
+        fs::write(&file, "ssh-ed25519 BBBB attacker").unwrap();
+
+        assert!(!store.put(path, &entry).unwrap());
+        assert_eq!(store.get_entry(&entry).unwrap(), None);
+    }
+
+    #[test]
+    fn prune_keeps_referenced_contents() {
+        let dir = tempdir().unwrap();
+        let mut store = store(dir.path());
+        let opts = EntryOptions::default();
+        let mut entries = Vec::new();
+        for name in ["a", "b", "c"] {
+            let file = dir.path().join(name);
+            fs::write(&file, name.repeat(100)).unwrap();
+            let entry = Entry::from_path(file.to_str().unwrap(), &opts).unwrap();
+            store.put(file.to_str().unwrap(), &entry).unwrap();
+            entries.push(entry);
+        }
+        let keep: BTreeSet<Key> = [(
+            HashAlgorithm::Sha256,
+            entries[0].digest().unwrap().to_string(),
+        )]
+        .into();
+        store.cfg.max_size = Some(0);
+
+        let pruned = store.prune(&keep).unwrap();
+        assert_eq!(pruned.objects, 2);
+        assert!(store.get_entry(&entries[0]).unwrap().is_some());
+        assert!(store.get_entry(&entries[1]).unwrap().is_none());
+    }
+}
@@ -0,0 +1,186 @@
+// Expansion of watch paths (files and directories) into regular files
+use crate::config::JobConfig;
+use crate::integrity::IgnoreMatcher;
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Options controlling how directories listed in `watch_paths` are walked.
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    /// Maximum depth below each watch path (`None` = unlimited).
+    pub max_depth: Option<usize>,
+
+    /// Do not descend into directories that live on another filesystem.
+    pub one_file_system: bool,
+
+    /// Paths matching the job's `ignore_patterns` are skipped (and not descended into).
+    pub ignore: IgnoreMatcher,
+}
+
+impl WalkOptions {
+    /// Build the walk options for a configured job. Fails if an ignore pattern is invalid.
+    pub fn for_job(job_cfg: &JobConfig) -> Result<Self> {
+        Ok(WalkOptions {
+            max_depth: job_cfg.max_depth,
+            one_file_system: job_cfg.one_file_system,
+            ignore: IgnoreMatcher::new(&job_cfg.ignore_patterns)?,
+        })
+    }
+}
+
+/// Expand `paths` into a sorted, de-duplicated list of files.
+///
+/// Paths that are not directories are returned as-is, even if missing. Directories
+/// are walked recursively without following symlinks, and only regular files are
+/// kept. Anything matched by `opts.ignore` is left out, and so is whatever cannot be
+/// read for lack of permission (logged), so one unreadable subdirectory does not
+/// fail the whole job.
+pub fn collect_files(paths: &[String], opts: &WalkOptions) -> Result<Vec<String>> {
+    let mut files = BTreeSet::new();
+
+    for path in paths {
+        let is_dir = fs::metadata(path).map(|m| m.is_dir()).unwrap_or(false);
+        if opts.ignore.is_ignored(Path::new(path), is_dir) {
+            continue;
+        }
+        if !is_dir {
+            files.insert(path.clone());
+            continue;
+        }
+
+        let mut walker = WalkDir::new(path)
+            .follow_links(false)
+            .same_file_system(opts.one_file_system);
+        if let Some(depth) = opts.max_depth {
+            walker = walker.max_depth(depth);
+        }
+
+        let walker = walker
+            .into_iter()
+            .filter_entry(|e| !opts.ignore.is_ignored(e.path(), e.file_type().is_dir()));
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => match e.io_error().map(|e| e.kind()) {
+                    // A subdirectory removed while we walk
+                    Some(ErrorKind::NotFound) => continue,
+                    Some(ErrorKind::PermissionDenied) => {
+                        let at = e.path().unwrap_or(Path::new(path));
+                        eprintln!("Skipping {:?}: permission denied", at);
+                        continue;
+                    }
+                    _ => return Err(e).with_context(|| format!("walking directory {}", path)),
+                },
+            };
+            if entry.file_type().is_file() {
+                files.insert(entry.path().to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    Ok(files.into_iter().collect())
+}
+
+/// True if `path` belongs to a job watching `paths`: it is one of the watch paths or
+/// lies below one within `max_depth`, stays on the same filesystem when
+/// `one_file_system` is set, and is not ignored. Used to decide whether a file that
+/// appears at runtime would have been part of the baseline.
+pub fn is_watched(paths: &[String], path: &Path, opts: &WalkOptions) -> bool {
+    if opts.ignore.is_ignored(path, path.is_dir()) {
+        return false;
+    }
+    paths.iter().any(|root| {
+        let Ok(rel) = path.strip_prefix(root) else {
+            return false;
+        };
+        if let Some(depth) = opts.max_depth
+            && rel.components().count() > depth
+        {
+            return false;
+        }
+        if opts.one_file_system
+            && let (Ok(root_meta), Ok(meta)) = (fs::metadata(root), fs::symlink_metadata(path))
+        {
+            return root_meta.dev() == meta.dev();
+        }
+        true
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WalkOptions, collect_files, is_watched};
+    use crate::integrity::IgnoreMatcher;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    #[test]
+    fn walks_directories_recursively() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("sub/deeper")).unwrap();
+        fs::write(dir.path().join("top.txt"), "a").unwrap();
+        fs::write(dir.path().join("sub/mid.txt"), "b").unwrap();
+        fs::write(dir.path().join("sub/deeper/low.txt"), "c").unwrap();
+
+        let root = dir.path().to_str().unwrap().to_string();
+        let files = collect_files(&[root], &WalkOptions::default()).unwrap();
+        assert_eq!(files.len(), 3);
+        assert!(files.iter().all(|f| !f.ends_with("sub")));
+    }
+
+    #[test]
+    fn max_depth_limits_recursion() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("top.txt"), "a").unwrap();
+        fs::write(dir.path().join("sub/mid.txt"), "b").unwrap();
+
+        let opts = WalkOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let root = dir.path().to_str().unwrap().to_string();
+        let files = collect_files(&[root], &opts).unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].ends_with("top.txt"));
+    }
+
+    #[test]
+    fn ignored_files_and_directories_are_skipped() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("node_modules/pkg")).unwrap();
+        fs::write(dir.path().join("index.php"), "a").unwrap();
+        fs::write(dir.path().join(".index.php.swp"), "b").unwrap();
+        fs::write(dir.path().join("node_modules/pkg/x.js"), "c").unwrap();
+
+        let opts = WalkOptions {
+            ignore: IgnoreMatcher::new(&["*.swp".into(), "node_modules/".into()]).unwrap(),
+            ..Default::default()
+        };
+        let root = dir.path().to_str().unwrap().to_string();
+        let files = collect_files(&[root], &opts).unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].ends_with("index.php"));
+    }
+
+    #[test]
+    fn is_watched_respects_roots_depth_and_ignores() {
+        let opts = WalkOptions {
+            max_depth: Some(2),
+            ignore: IgnoreMatcher::new(&["*.swp".into()]).unwrap(),
+            ..Default::default()
+        };
+        let roots = vec!["/var/www/html".to_string(), "/etc/passwd".to_string()];
+        assert!(is_watched(&roots, Path::new("/etc/passwd"), &opts));
+        assert!(is_watched(&roots, Path::new("/var/www/html/a/b.php"), &opts));
+        assert!(!is_watched(&roots, Path::new("/var/www/html/a/b/c.php"), &opts));
+        assert!(!is_watched(&roots, Path::new("/var/www/html/.a.swp"), &opts));
+        assert!(!is_watched(&roots, Path::new("/etc/shadow"), &opts));
+    }
+}
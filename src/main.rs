@@ -1,8 +1,9 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use watchdogfs::{
     cli,
     config::{self, Config},
-    integrity, logger, selfcheck, watcher,
+    integrity::{self, history},
+    logger, selfcheck, watcher,
 };
 
 fn main() -> Result<()> {
@@ -50,6 +51,189 @@ fn main() -> Result<()> {
         cli::Commands::Start { daemon } => {
             watcher::start(daemon)?;
         }
+
+        cli::Commands::Check { job, format } => {
+            let report = config::load(&args.config)
+                .and_then(|cfg| integrity::check::run(&cfg, job.as_deref()));
+            let code = match report {
+                Ok(report) => {
+                    match format {
+                        cli::ReportFormat::Human => print!("{}", report.to_human()),
+                        cli::ReportFormat::Json => {
+                            println!("{}", serde_json::to_string_pretty(&report)?)
+                        }
+                    }
+                    report.exit_code()
+                }
+                Err(e) => {
+                    eprintln!("❌ {:#}", e);
+                    integrity::check::EXIT_ERROR
+                }
+            };
+            std::process::exit(code);
+        }
+
+        cli::Commands::Diff {
+            from,
+            to,
+            format,
+            paths,
+            exclude,
+            attributes,
+            verify,
+        } => {
+            let attrs = if attributes.is_empty() {
+                config::default_attributes()
+            } else {
+                attributes
+            };
+            let result = integrity::diff::PathFilter::new(&paths, &exclude).and_then(|filter| {
+                let keys = if verify {
+                    let cfg = config::load(&args.config)?;
+                    Some(
+                        integrity::signing::Keys::for_config(&cfg)?
+                            .context("--verify needs a `signing` section in the config")?,
+                    )
+                } else {
+                    None
+                };
+                integrity::diff::run(&from, &to, &attrs, &filter, keys.as_ref())
+            });
+            let code = match result {
+                Ok(diff) => {
+                    match format {
+                        cli::DiffFormat::Text => print!("{}", diff.to_text()),
+                        cli::DiffFormat::Json => {
+                            println!("{}", serde_json::to_string_pretty(&diff)?)
+                        }
+                        cli::DiffFormat::Markdown => print!("{}", diff.to_markdown()),
+                    }
+                    diff.exit_code()
+                }
+                Err(e) => {
+                    eprintln!("❌ {:#}", e);
+                    integrity::diff::EXIT_ERROR
+                }
+            };
+            std::process::exit(code);
+        }
+
+        cli::Commands::Nagios { job } => {
+            let (output, code) = match config::load(&args.config) {
+                Ok(cfg) => match integrity::check::run(&cfg, job.as_deref()) {
+                    Ok(report) => integrity::nagios::render(&report, &cfg),
+                    Err(e) => integrity::nagios::unknown(&e),
+                },
+                Err(e) => integrity::nagios::unknown(&e),
+            };
+            print!("{}", output);
+            std::process::exit(code);
+        }
+
+        cli::Commands::Accept {
+            job,
+            interactive,
+            paths,
+        } => {
+            let cfg = config::load(&args.config)?;
+            let (accepted, unmatched) =
+                integrity::accept::run(&cfg, &job, &paths, interactive)?;
+            for path in &unmatched {
+                eprintln!("⚠️  No pending change under {}", path);
+            }
+            for change in &accepted {
+                println!("✅ Accepted {} {}", change.kind, change.path);
+            }
+            if accepted.is_empty() {
+                println!("Nothing accepted; baseline of job '{}' unchanged", job);
+            }
+        }
+
+        cli::Commands::History { action } => {
+            let cfg = config::load(&args.config)?;
+            match action {
+                cli::HistoryAction::List { job } => {
+                    let baseline_file = integrity::baseline_path(&job);
+                    for snapshot in history::list(&history::history_dir(&job))? {
+                        let summary = history::summary(&snapshot, &baseline_file)?;
+                        println!(
+                            "{}  {:<8}  {:>6} entries  written {}{}",
+                            snapshot.id,
+                            snapshot.reason,
+                            summary.entries,
+                            summary.written_at,
+                            if summary.active { "  (active)" } else { "" }
+                        );
+                    }
+                }
+                cli::HistoryAction::Diff { job, from, to } => {
+                    let changes = history::diff(&cfg, &job, &from, to.as_deref())?;
+                    for change in &changes {
+                        println!("{}", change.describe());
+                    }
+                    if changes.is_empty() {
+                        println!("No differences");
+                    }
+                }
+                cli::HistoryAction::Rollback { job, id } => {
+                    if !cfg.jobs.contains_key(&job) {
+                        anyhow::bail!("No job named '{}' in the configuration", job);
+                    }
+                    let dir = history::history_dir(&job);
+                    let snapshot = history::find(&dir, &id)?;
+                    let keys = integrity::signing::Keys::for_config(&cfg)?;
+                    history::rollback(
+                        &dir,
+                        &integrity::baseline_path(&job),
+                        &job,
+                        &snapshot,
+                        keys.as_ref(),
+                        cfg.history_keep,
+                    )?;
+                    println!(
+                        "✅ Baseline of job '{}' rolled back to {} ({})",
+                        job, snapshot.id, snapshot.reason
+                    );
+                }
+            }
+        }
+
+        cli::Commands::Store { action } => {
+            let cfg = config::load(&args.config)?;
+            let store =
+                integrity::store::ContentStore::new(cfg.content_store.clone().unwrap_or_default());
+            match action {
+                cli::StoreAction::Cat { hash, out } => {
+                    let data = store.find(&hash)?;
+                    match out {
+                        Some(path) => {
+                            std::fs::write(&path, &data)
+                                .with_context(|| format!("writing {}", path))?;
+                            println!("✅ Wrote {} bytes to {}", data.len(), path);
+                        }
+                        None => std::io::Write::write_all(&mut std::io::stdout(), &data)?,
+                    }
+                }
+                cli::StoreAction::Prune => {
+                    let pruned = store.prune(&integrity::store::referenced(&cfg))?;
+                    println!(
+                        "✅ Removed {} object(s), {} bytes",
+                        pruned.objects, pruned.bytes
+                    );
+                }
+            }
+        }
+
+        cli::Commands::Keygen { method, out } => {
+            for path in integrity::signing::generate_key(method, std::path::Path::new(&out))? {
+                println!("✅ Wrote {}", path.display());
+            }
+        }
+
+        cli::Commands::Resign { job } => {
+            let cfg = config::load(&args.config)?;
+            integrity::resign_baselines(&cfg, job.as_deref())?;
+        }
     }
 
     Ok(())
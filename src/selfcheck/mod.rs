@@ -1,5 +1,5 @@
+use crate::integrity::calculate_checksum;
 use anyhow::{Context, Result};
-use sha2::{Digest, Sha256};
 use std::{env, fs};
 
 /// Compute SHA256 over the current executable, compare to the contents of `path`.
@@ -14,14 +14,10 @@ pub fn verify(self_integrity_path: &str) -> Result<()> {
     // 2) Locate our own binary
     let exe_path = env::current_exe().context("getting path to current executable")?;
 
-    let data = fs::read(&exe_path)
+    // 3) Compute SHA256 (streamed, the binary is never loaded whole)
+    let actual_hex = calculate_checksum(&exe_path)
         .with_context(|| format!("reading executable `{}`", exe_path.display()))?;
 
-    // 3) Compute SHA256
-    let mut hasher = Sha256::new();
-    hasher.update(&data);
-    let actual_hex = format!("{:x}", hasher.finalize());
-
     // 4) Compare
     if expected_hex != actual_hex {
         anyhow::bail!(
@@ -1,4 +1,155 @@
 // Common utility functions
+use anyhow::{Context, Result};
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
 pub fn example_util() {
     println!("Example utility function");
 }
+
+/// Atomically replace `path` with `data`.
+///
+/// The bytes go to a temporary file in the same directory, which is fsynced
+/// and renamed over `path`; the directory is then fsynced so the rename itself
+/// is durable. After a crash `path` holds either the old or the new content,
+/// never a truncated mix. The temporary file has a random name and is created
+/// exclusively, so it never follows a planted symlink and concurrent writers
+/// never share one.
+pub fn write_atomic<P: AsRef<Path>>(path: P, data: &[u8]) -> Result<()> {
+    let path = path.as_ref();
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("{:?} has no file name", path))?;
+    let (mut f, tmp) = loop {
+        let tmp = dir.join(temp_name(file_name));
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp)
+        {
+            Ok(f) => break (f, tmp),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e).with_context(|| format!("creating {:?}", tmp)),
+        }
+    };
+
+    let result = (|| -> Result<()> {
+        f.write_all(data)
+            .with_context(|| format!("writing {:?}", tmp))?;
+        f.sync_all().with_context(|| format!("syncing {:?}", tmp))?;
+        fs::rename(&tmp, path).with_context(|| format!("renaming {:?} to {:?}", tmp, path))?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+        return result;
+    }
+
+    File::open(dir)
+        .and_then(|d| d.sync_all())
+        .with_context(|| format!("syncing directory {:?}", dir))?;
+    Ok(())
+}
+
+/// `.<name>.tmp<random>`, the name of a temporary file next to `name`.
+fn temp_name(name: &OsStr) -> OsString {
+    let mut tmp = OsString::from(".");
+    tmp.push(name);
+    tmp.push(format!(".tmp{:016x}", fastrand::u64(..)));
+    tmp
+}
+
+/// Take an exclusive `flock` on `path` (created if missing), waiting while another
+/// process holds it. The lock is released when the returned file is dropped.
+pub fn lock_file<P: AsRef<Path>>(path: P) -> Result<File> {
+    let path = path.as_ref();
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("opening lock file {:?}", path))?;
+    // SAFETY: the descriptor is owned by `file`, which outlives the call
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(std::io::Error::last_os_error()).with_context(|| format!("locking {:?}", path));
+    }
+    Ok(file)
+}
+
+/// Lowercase hex encoding of `bytes`.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut s, b| {
+            let _ = write!(s, "{:02x}", b);
+            s
+        })
+}
+
+/// Decode hex (either case); `None` if `s` is not valid hex.
+pub fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_hex, temp_name, to_hex, write_atomic};
+    use std::ffi::OsStr;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn write_atomic_replaces_and_leaves_no_temp_file() {
+        let dir = tempdir().unwrap();
+        let target = dir.path().join("baseline_job.json");
+        fs::write(&target, "old").unwrap();
+
+        write_atomic(&target, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn write_atomic_does_not_follow_a_planted_symlink() {
+        let dir = tempdir().unwrap();
+        let target = dir.path().join("baseline_job.json");
+        let victim = dir.path().join("shadow");
+        fs::write(&victim, "root:x:0:0").unwrap();
+        fastrand::seed(7);
+        let planted = dir.path().join(temp_name(OsStr::new("baseline_job.json")));
+        std::os::unix::fs::symlink(&victim, &planted).unwrap();
+
+        fastrand::seed(7);
+        write_atomic(&target, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+        assert_eq!(fs::read_to_string(&victim).unwrap(), "root:x:0:0");
+        assert!(
+            fs::symlink_metadata(&planted)
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+    }
+
+    #[test]
+    fn hex_roundtrip() {
+        let bytes = b"system_u:object_r:etc_t:s0\0";
+        assert_eq!(from_hex(&to_hex(bytes)).unwrap(), bytes);
+        assert!(from_hex("abc").is_none());
+        assert!(from_hex("zz").is_none());
+    }
+}
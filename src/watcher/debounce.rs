@@ -0,0 +1,195 @@
+// Per-path debouncing of inotify events (`watcher.debounce`)
+use crate::config::DebounceMode;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// A pending path is handled at the latest this many `delay`s after its first
+/// unhandled event, even if events keep arriving, so a busy file cannot hide
+/// changes forever.
+const MAX_WAIT_DELAYS: u32 = 10;
+
+#[derive(Debug)]
+struct PathState {
+    /// Last event (trailing, both) or last handled event (leading)
+    last: Instant,
+    /// Events arrived that still need handling once the path is quiet
+    pending: bool,
+    /// First event since the path was last handled, while `pending`
+    first_pending: Instant,
+}
+
+/// Decides when a path that keeps receiving events is handled. Time is passed
+/// in, so the behaviour does not depend on the clock.
+#[derive(Debug)]
+pub struct Debouncer {
+    mode: DebounceMode,
+    delay: Duration,
+    paths: HashMap<String, PathState>,
+}
+
+impl Debouncer {
+    pub fn new(mode: DebounceMode, delay: Duration) -> Self {
+        Debouncer {
+            mode,
+            delay,
+            paths: HashMap::new(),
+        }
+    }
+
+    /// Record an event for `path` at `now`. Returns whether the path should be
+    /// handled right away; otherwise it is handled by `due` if the mode asks for it.
+    pub fn event(&mut self, path: &str, now: Instant) -> bool {
+        let state = self.paths.get_mut(path);
+        let quiet = state
+            .as_ref()
+            .is_none_or(|s| now.duration_since(s.last) >= self.delay);
+        let handle = quiet && self.mode != DebounceMode::Trailing;
+        match state {
+            // Leading measures the window from the handled event, so a steady
+            // stream of events is still handled every `delay`
+            Some(_) if self.mode == DebounceMode::Leading && !handle => {}
+            Some(state) => {
+                let was_pending = state.pending;
+                state.last = now;
+                state.pending = !handle && self.mode != DebounceMode::Leading;
+                if state.pending && !was_pending {
+                    state.first_pending = now;
+                }
+            }
+            None => {
+                self.paths.insert(
+                    path.to_string(),
+                    PathState {
+                        last: now,
+                        pending: !handle,
+                        first_pending: now,
+                    },
+                );
+            }
+        }
+        handle
+    }
+
+    /// Drop whatever is pending for `path`, e.g. a temp file that was renamed away.
+    pub fn forget(&mut self, path: &str) {
+        self.paths.remove(path);
+    }
+
+    /// Paths whose events stopped at least `delay` before `now`, or that have been
+    /// pending for `MAX_WAIT_DELAYS` times `delay`, and still need handling, sorted.
+    /// Paths that went quiet or are handled are forgotten.
+    pub fn due(&mut self, now: Instant) -> Vec<String> {
+        let max_wait = self.delay * MAX_WAIT_DELAYS;
+        let mut due = Vec::new();
+        self.paths.retain(|path, state| {
+            let overdue = state.pending && now.duration_since(state.first_pending) >= max_wait;
+            if now.duration_since(state.last) < self.delay && !overdue {
+                return true;
+            }
+            if state.pending {
+                due.push(path.clone());
+            }
+            false
+        });
+        due.sort();
+        due
+    }
+
+    /// When the next pending path becomes due, if any.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.paths
+            .values()
+            .filter(|s| s.pending)
+            .map(|s| (s.last + self.delay).min(s.first_pending + self.delay * MAX_WAIT_DELAYS))
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Debouncer;
+    use crate::config::DebounceMode;
+    use std::time::{Duration, Instant};
+
+    const DELAY: Duration = Duration::from_millis(500);
+
+    /// Events for one path at the given offsets (ms); returns the offsets at which
+    /// the path is handled, checking `due` every 100 ms up to `until`.
+    fn handled_at(mode: DebounceMode, events: &[u64], until: u64) -> Vec<u64> {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut debouncer = Debouncer::new(mode, DELAY);
+        let mut handled = Vec::new();
+        for ms in (0..=until).step_by(50) {
+            if events.contains(&ms) && debouncer.event("/etc/app.conf", at(ms)) {
+                handled.push(ms);
+            }
+            if ms % 100 == 0 && !debouncer.due(at(ms)).is_empty() {
+                handled.push(ms);
+            }
+        }
+        handled
+    }
+
+    #[test]
+    fn leading_handles_the_first_event_only() {
+        assert_eq!(handled_at(DebounceMode::Leading, &[0, 50, 300], 2000), [0]);
+        // A steady stream is still handled every `delay`
+        assert_eq!(
+            handled_at(DebounceMode::Leading, &[0, 200, 400, 600], 2000),
+            [0, 600]
+        );
+    }
+
+    #[test]
+    fn trailing_waits_for_the_last_event() {
+        // Quiet from 300 on, so handled once the check at 800 sees it
+        assert_eq!(
+            handled_at(DebounceMode::Trailing, &[0, 50, 300], 2000),
+            [800]
+        );
+        assert_eq!(
+            handled_at(DebounceMode::Trailing, &[0, 1000], 2000),
+            [500, 1500]
+        );
+    }
+
+    #[test]
+    fn trailing_is_not_starved_by_a_busy_path() {
+        // An event every 200 ms never leaves the path quiet for 500 ms, yet it is
+        // handled once it has waited ten delays
+        let events: Vec<u64> = (0..=6000).step_by(200).collect();
+        assert_eq!(handled_at(DebounceMode::Trailing, &events, 6000), [5000]);
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(DebounceMode::Trailing, DELAY);
+        for ms in (0..=5000).step_by(200) {
+            debouncer.event("/var/log/app.log", start + Duration::from_millis(ms));
+        }
+        assert_eq!(debouncer.next_due(), Some(start + DELAY * 10));
+    }
+
+    #[test]
+    fn both_handles_first_and_last() {
+        assert_eq!(
+            handled_at(DebounceMode::Both, &[0, 50, 300], 2000),
+            [0, 800]
+        );
+        // A lone event is not handled twice
+        assert_eq!(handled_at(DebounceMode::Both, &[0], 2000), [0]);
+    }
+
+    #[test]
+    fn next_due_tracks_pending_paths() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(DebounceMode::Trailing, DELAY);
+        assert_eq!(debouncer.next_due(), None);
+        debouncer.event("/a", start);
+        debouncer.event("/b", start + Duration::from_millis(100));
+        assert_eq!(debouncer.next_due(), Some(start + DELAY));
+        assert_eq!(debouncer.due(start + DELAY), ["/a"]);
+        assert_eq!(
+            debouncer.next_due(),
+            Some(start + Duration::from_millis(600))
+        );
+    }
+}
@@ -1,19 +1,36 @@
+mod debounce;
+mod rename;
+
 use crate::alerts::dispatch;
-use crate::config::{Config, JobConfig};
-use crate::integrity::{Baseline, calculate_checksum, generate_map};
+use crate::config::{self, BaselineUpdate, Config, JobConfig, Remediation, Schedule};
+use crate::integrity::hashcache::{self, HashCache};
+use crate::integrity::restore::{self, RestoreGuard};
+use crate::integrity::semantic::{self, Finding, Severity};
+use crate::integrity::signing::Keys;
+use crate::integrity::store::{self, ContentStore};
+use crate::integrity::textdiff::TextDiff;
+use crate::integrity::{
+    Baseline, Change, ChangeKind, Digests, Entry, EntryOptions, SCHEMA_VERSION, WalkOptions,
+    baseline_path, collect_files, compare, create_baseline, generate_map_cached, history,
+    is_watched, lock_baseline, read_baseline_unverified, save_baseline,
+};
 use anyhow::{Context, Result};
+use chrono::Local;
+use debounce::Debouncer;
 use notify::{
-    Config as NotifyConfig, Event, RecommendedWatcher, RecursiveMode, Result as NotifyResult,
-    Watcher,
+    Config as NotifyConfig, ErrorKind as NotifyErrorKind, Event, EventKind, RecommendedWatcher,
+    RecursiveMode, Result as NotifyResult, Watcher,
+    event::{ModifyKind, RenameMode},
 };
-use serde_yaml;
+use rename::RenameTracker;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs,
+    os::unix::fs::MetadataExt,
     path::{Path, PathBuf},
-    sync::mpsc::{self, Receiver, Sender},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
     thread,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
 /// Start all named jobs. If `daemon == true`, also run a loop that watches `config.yaml`
@@ -32,15 +49,27 @@ pub fn start(daemon: bool) -> Result<()> {
     let mut last_modified = fs::metadata(config_path)?
         .modified()
         .context("getting initial config.yaml modified time")?;
-    let mut current_cfg = load_config(config_path)?;
+    let mut current_cfg = config::load(config_path)?;
+    let mut keys = Keys::for_config(&current_cfg)?;
 
     for (job_name, job_cfg) in current_cfg.jobs.clone() {
-        let baseline_map = load_or_generate_baseline(&job_name, &job_cfg)?;
+        let baseline_map =
+            load_or_generate_baseline(&job_name, &job_cfg, &current_cfg, keys.as_ref())?;
         let (tx, rx) = mpsc::channel();
-        let job_handle = spawn_job_thread(job_name.clone(), job_cfg.clone(), baseline_map, rx);
+        let job_handle = spawn_job_thread(
+            job_name.clone(),
+            job_cfg.clone(),
+            baseline_map,
+            keys.clone(),
+            ContentStore::for_job(&current_cfg, &job_cfg),
+            rx,
+        );
         job_handles.insert(job_name, (tx, job_handle));
     }
 
+    prune_store(&current_cfg);
+    let mut last_prune = Instant::now();
+
     // 2) If not a daemon, return now (threads will end when main exits)
     if !daemon {
         return Ok(());
@@ -50,6 +79,11 @@ pub fn start(daemon: bool) -> Result<()> {
     loop {
         thread::sleep(Duration::from_secs(2));
 
+        if last_prune.elapsed() >= STORE_PRUNE_INTERVAL {
+            prune_store(&current_cfg);
+            last_prune = Instant::now();
+        }
+
         // Check if config.yaml has been updated on disk
         let meta = match fs::metadata(config_path) {
             Ok(m) => m,
@@ -73,22 +107,34 @@ pub fn start(daemon: bool) -> Result<()> {
         last_modified = modified;
 
         // Reload the YAML
-        let new_cfg = match load_config(config_path) {
+        let new_cfg = match config::load(config_path) {
             Ok(cfg) => cfg,
             Err(e) => {
                 eprintln!("Failed to reload {}: {:?}", config_path, e);
                 continue;
             }
         };
+        // New keys or a moved content store apply to every job, so a change restarts them all
+        let signing_changed = new_cfg.signing != current_cfg.signing;
+        let shared_changed = signing_changed || new_cfg.content_store != current_cfg.content_store;
+        if signing_changed {
+            keys = match Keys::for_config(&new_cfg) {
+                Ok(k) => k,
+                Err(e) => {
+                    eprintln!("Failed to reload {}: {:?}", config_path, e);
+                    continue;
+                }
+            };
+        }
 
         // ===== 1) Remove jobs that no longer exist in new_cfg =====
         for existing_job in job_handles.keys().cloned().collect::<Vec<_>>() {
-            if !new_cfg.jobs.contains_key(&existing_job) {
-                if let Some((stop_tx, handle)) = job_handles.remove(&existing_job) {
-                    let _ = stop_tx.send(());
-                    let _ = handle.join();
-                    println!("Stopped job '{}'", existing_job);
-                }
+            if !new_cfg.jobs.contains_key(&existing_job)
+                && let Some((stop_tx, handle)) = job_handles.remove(&existing_job)
+            {
+                let _ = stop_tx.send(());
+                let _ = handle.join();
+                println!("Stopped job '{}'", existing_job);
             }
         }
 
@@ -96,32 +142,33 @@ pub fn start(daemon: bool) -> Result<()> {
         for job_name in current_cfg.jobs.keys() {
             if let (Some(old_cfg), Some(new_job_cfg)) =
                 (current_cfg.jobs.get(job_name), new_cfg.jobs.get(job_name))
+                && (old_cfg != new_job_cfg || shared_changed)
             {
-                if old_cfg != new_job_cfg {
-                    // Stop the old thread
-                    if let Some((stop_tx, handle)) = job_handles.remove(job_name) {
-                        let _ = stop_tx.send(());
-                        let _ = handle.join();
-                        println!("Reloaded job '{}' due to config change", job_name);
+                // Stop the old thread
+                if let Some((stop_tx, handle)) = job_handles.remove(job_name) {
+                    let _ = stop_tx.send(());
+                    let _ = handle.join();
+                    println!("Reloaded job '{}' due to config change", job_name);
+                }
+                // Spawn a new thread with updated config
+                match load_or_generate_baseline(job_name, new_job_cfg, &new_cfg, keys.as_ref()) {
+                    Ok(baseline_map) => {
+                        let (tx, rx) = mpsc::channel();
+                        let handle = spawn_job_thread(
+                            job_name.clone(),
+                            new_job_cfg.clone(),
+                            baseline_map,
+                            keys.clone(),
+                            ContentStore::for_job(&new_cfg, new_job_cfg),
+                            rx,
+                        );
+                        job_handles.insert(job_name.clone(), (tx, handle));
                     }
-                    // Spawn a new thread with updated config
-                    match load_or_generate_baseline(job_name, new_job_cfg) {
-                        Ok(baseline_map) => {
-                            let (tx, rx) = mpsc::channel();
-                            let handle = spawn_job_thread(
-                                job_name.clone(),
-                                new_job_cfg.clone(),
-                                baseline_map,
-                                rx,
-                            );
-                            job_handles.insert(job_name.clone(), (tx, handle));
-                        }
-                        Err(e) => {
-                            eprintln!(
-                                "Failed to regenerate baseline for changed job '{}': {:?}",
-                                job_name, e
-                            );
-                        }
+                    Err(e) => {
+                        eprintln!(
+                            "Failed to regenerate baseline for changed job '{}': {:?}",
+                            job_name, e
+                        );
                     }
                 }
             }
@@ -130,11 +177,17 @@ pub fn start(daemon: bool) -> Result<()> {
         // ===== 3) Add any new jobs in new_cfg =====
         for (job_name, job_cfg) in new_cfg.jobs.clone() {
             if !job_handles.contains_key(&job_name) {
-                match load_or_generate_baseline(&job_name, &job_cfg) {
+                match load_or_generate_baseline(&job_name, &job_cfg, &new_cfg, keys.as_ref()) {
                     Ok(baseline_map) => {
                         let (tx, rx) = mpsc::channel();
-                        let handle =
-                            spawn_job_thread(job_name.clone(), job_cfg.clone(), baseline_map, rx);
+                        let handle = spawn_job_thread(
+                            job_name.clone(),
+                            job_cfg.clone(),
+                            baseline_map,
+                            keys.clone(),
+                            ContentStore::for_job(&new_cfg, &job_cfg),
+                            rx,
+                        );
                         job_handles.insert(job_name.clone(), (tx, handle));
                         println!("Started job '{}'", job_name);
                     }
@@ -153,162 +206,824 @@ pub fn start(daemon: bool) -> Result<()> {
     }
 }
 
-/// Load + parse `config.yaml`.
-fn load_config(path: &str) -> Result<Config> {
-    let s = fs::read_to_string(path).with_context(|| format!("reading config file {:?}", path))?;
-    let cfg: Config = serde_yaml::from_str(&s).context("parsing YAML config")?;
-    Ok(cfg)
+/// How often the daemon applies the content store's retention limits.
+const STORE_PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+fn prune_store(cfg: &Config) {
+    match store::prune_config(cfg) {
+        Ok(pruned) if pruned.objects > 0 => println!(
+            "Pruned {} object(s) ({} bytes) from the content store",
+            pruned.objects, pruned.bytes
+        ),
+        Ok(_) => {}
+        Err(e) => eprintln!("Failed to prune the content store: {:?}", e),
+    }
 }
 
 /// For a given job, either load its existing `baseline_<job_name>.json` or generate a fresh one.
-fn load_or_generate_baseline(job_name: &str, job_cfg: &JobConfig) -> Result<Baseline> {
-    let filename = format!("baseline_{}.json", job_name);
-    if Path::new(&filename).exists() {
-        // Load existing JSON
-        let s = fs::read_to_string(&filename)?;
-        let baseline: Baseline =
-            serde_json::from_str(&s).with_context(|| format!("parsing {}", filename))?;
-        Ok(baseline)
+///
+/// A freshly generated baseline is also recorded in the job's history.
+///
+/// With `keys`, the loaded baseline must carry a valid signature for this job. If it
+/// does not, or if the baseline is missing (regenerating it would sign whatever is on
+/// disk now), a `tampered` alert is dispatched and the job is refused (an error is
+/// returned).
+fn load_or_generate_baseline(
+    job_name: &str,
+    job_cfg: &JobConfig,
+    cfg: &Config,
+    keys: Option<&Keys>,
+) -> Result<Baseline> {
+    let filename = baseline_path(job_name);
+    if filename.exists() {
+        // Load existing JSON, upgrading files written in an older format
+        let file = read_baseline_unverified(&filename)?;
+        if let Some(keys) = keys
+            && let Err(e) = file.verify(keys, job_name)
+        {
+            let path = filename.to_string_lossy().into_owned();
+            dispatch(&job_cfg.alerts, job_name, &Change::tampered(path));
+            return Err(e).with_context(|| {
+                format!(
+                    "baseline {:?} of job '{}' failed verification",
+                    filename, job_name
+                )
+            });
+        }
+        if file.header.schema_version < SCHEMA_VERSION {
+            save_baseline(&filename, &file.entries, job_name, job_cfg, keys)
+                .with_context(|| format!("migrating baseline for job '{}'", job_name))?;
+            println!(
+                "Job '{}': migrated {:?} to baseline schema version {}",
+                job_name, filename, SCHEMA_VERSION
+            );
+        }
+        Ok(file.entries)
+    } else if keys.is_some() {
+        let path = filename.to_string_lossy().into_owned();
+        dispatch(&job_cfg.alerts, job_name, &Change::tampered(path));
+        anyhow::bail!(
+            "baseline {:?} of job '{}' is missing; with signing configured it is only \
+             created by `watchdogfs baseline`",
+            filename,
+            job_name
+        )
     } else {
         // Generate new baseline JSON from scratch
-        let baseline_map = generate_map(&job_cfg.watch_paths)
-            .with_context(|| format!("generating baseline for job '{}'", job_name))?;
-        let json = serde_json::to_string_pretty(&baseline_map)
-            .with_context(|| format!("serializing baseline for job '{}'", job_name))?;
-        fs::write(&filename, &json).with_context(|| format!("writing {}", filename))?;
+        let walk_opts = WalkOptions::for_job(job_cfg)
+            .with_context(|| format!("invalid ignore_patterns for job '{}'", job_name))?;
+        let entry_opts = EntryOptions::for_job(job_cfg);
+        let mut cache = HashCache::load(hashcache::path_for(job_name), keys);
+        let baseline_map =
+            generate_map_cached(&job_cfg.watch_paths, &walk_opts, &entry_opts, &mut cache)
+                .with_context(|| format!("generating baseline for job '{}'", job_name))?;
+        if let Err(e) = cache.save() {
+            eprintln!("Job '{}' failed to write its hash cache: {:?}", job_name, e);
+        }
+        create_baseline(&filename, &baseline_map, job_name, job_cfg, keys)
+            .with_context(|| format!("writing baseline for job '{}'", job_name))?;
+        if let Err(e) = history::record(
+            &history::history_dir(job_name),
+            &filename,
+            history::REASON_BASELINE,
+            cfg.history_keep,
+        ) {
+            eprintln!(
+                "Job '{}' failed to record baseline history: {:?}",
+                job_name, e
+            );
+        }
         Ok(baseline_map)
     }
 }
 
+/// Everything a running job owns: its configuration, its in-memory baseline and
+/// the file that baseline is persisted to.
+struct JobState {
+    name: String,
+    cfg: JobConfig,
+    walk_opts: WalkOptions,
+    entry_opts: EntryOptions,
+    baseline: Baseline,
+    /// Where `baseline` is persisted (`baseline_<job_name>.json`)
+    baseline_file: PathBuf,
+    /// Signs `baseline_file` on every write, if signing is configured
+    keys: Option<Keys>,
+    /// Modification time of `baseline_file` when we last read or wrote it
+    baseline_mtime: Option<SystemTime>,
+    /// Keeps copies of recorded and changed contents, if the job has `store_contents`
+    store: Option<ContentStore>,
+    /// Recent restores per path, for `remediation: restore`
+    restore_guard: RestoreGuard,
+    /// Adds diffs of modified text files to alerts, if the job has `text_diff`
+    text_diff: Option<TextDiff>,
+    /// Entries of files hashed earlier, reused while their stat is unchanged (polling only)
+    hash_cache: Option<HashCache>,
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl JobState {
+    /// Alert on every change, restore tampered files if the job asks for it, then apply
+    /// the job's `baseline_update` policy: either fold the changes into the baseline and
+    /// persist it atomically, or leave the baseline untouched so the changes keep
+    /// alerting until they are accepted.
+    fn handle_changes(&mut self, mut changes: Vec<Change>) {
+        if changes.is_empty() {
+            return;
+        }
+        if let Some(store) = &self.store {
+            for change in &changes {
+                if let Some(entry) = &change.new
+                    && let Err(e) = store.put(&change.path, entry)
+                {
+                    eprintln!(
+                        "Job '{}' failed to store content of {}: {:?}",
+                        self.name, change.path, e
+                    );
+                }
+            }
+            for change in changes.iter_mut() {
+                if let Some(text_diff) = &self.text_diff
+                    && matches!(change.kind, ChangeKind::Modified | ChangeKind::Replaced)
+                {
+                    match text_diff.for_change(store, change) {
+                        Ok(diff) => change.diff = diff,
+                        Err(e) => eprintln!(
+                            "Job '{}' failed to diff {}: {:?}",
+                            self.name, change.path, e
+                        ),
+                    }
+                }
+                if self.cfg.semantic_findings {
+                    match semantic::for_change(store, change) {
+                        Ok(findings) => change.findings = findings,
+                        Err(e) => eprintln!(
+                            "Job '{}' failed to analyze {}: {:?}",
+                            self.name, change.path, e
+                        ),
+                    }
+                }
+            }
+        }
+        for change in &changes {
+            dispatch(&self.cfg.alerts, &self.name, change);
+        }
+        let restored = self.remediate(&changes);
+        let update = self.cfg.baseline_update == BaselineUpdate::UpdateOnAlert;
+        if !update && restored.is_empty() {
+            return;
+        }
+        let _lock = self.lock_baseline();
+        if update {
+            for change in &changes {
+                change.apply(&mut self.baseline);
+            }
+        }
+        // A restored file has the recorded content but a new inode and timestamps
+        for (path, entry) in &restored {
+            self.baseline.insert(path.clone(), entry.clone());
+        }
+        self.persist();
+    }
+
+    /// With `remediation: restore`, write back the recorded copy of every modified,
+    /// replaced, deleted or renamed-away file and send a `restored` alert. Returns the restored
+    /// paths with their entries as now on disk.
+    fn remediate(&mut self, changes: &[Change]) -> Vec<(String, Entry)> {
+        let mut restored = Vec::new();
+        let Some(store) = &self.store else {
+            return restored;
+        };
+        if self.cfg.remediation != Remediation::Restore {
+            return restored;
+        }
+        for change in changes {
+            let (path, tampered) = match change.kind {
+                ChangeKind::Modified | ChangeKind::Replaced => (&change.path, change.new.clone()),
+                ChangeKind::Deleted => (&change.path, None),
+                ChangeKind::Renamed => match &change.from {
+                    Some(from) => (from, None),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let Some(recorded) = &change.old else {
+                continue;
+            };
+            if !self.restore_guard.allow(path, Instant::now()) {
+                eprintln!(
+                    "Job '{}': not restoring {}: restored {} time(s) in the last {}s already",
+                    self.name,
+                    path,
+                    self.cfg.restore_limit.max_restores,
+                    self.cfg.restore_limit.window_secs
+                );
+                continue;
+            }
+            let result = restore::restore(path, recorded, store)
+                .and_then(|()| Entry::from_path(path, &self.entry_opts));
+            match result {
+                Ok(entry) => {
+                    let change = Change::restored(path.clone(), tampered, entry.clone());
+                    dispatch(&self.cfg.alerts, &self.name, &change);
+                    restored.push((path.clone(), entry));
+                }
+                Err(e) => eprintln!("Job '{}' failed to restore {}: {:?}", self.name, path, e),
+            }
+        }
+        restored
+    }
+
+    /// Empty the hash cache if `full_rehash_interval` passed since `last`, so the next
+    /// snapshot hashes every file. This catches content changed with the mtime put back,
+    /// and a tampered cache.
+    fn clear_hash_cache_if_due(&mut self, last: &mut Instant) {
+        if let Some(every) = self.cfg.watcher.full_rehash_interval
+            && last.elapsed() >= Duration::from_secs(every)
+            && let Some(cache) = &mut self.hash_cache
+        {
+            cache.clear();
+            *last = Instant::now();
+        }
+    }
+
+    /// Write the hash cache back, first dropping files not in `current` (a snapshot of
+    /// every watch path), if given.
+    fn save_hash_cache(&mut self, current: Option<&Baseline>) {
+        let Some(cache) = &mut self.hash_cache else {
+            return;
+        };
+        if let Some(current) = current {
+            cache.retain(|path| current.contains_key(path));
+        }
+        if let Err(e) = cache.save() {
+            eprintln!(
+                "Job '{}' failed to write its hash cache: {:?}",
+                self.name, e
+            );
+        }
+    }
+
+    /// Take the lock `watchdogfs accept` holds while it rewrites `baseline_file`, then
+    /// pick up anything it wrote meanwhile, so the update that follows lands on top of
+    /// the accepted baseline instead of overwriting it. Keep the returned lock until
+    /// `persist` is done; without one (the lock file cannot be created) the update
+    /// goes ahead unlocked.
+    fn lock_baseline(&mut self) -> Option<fs::File> {
+        let lock = match lock_baseline(&self.baseline_file) {
+            Ok(lock) => Some(lock),
+            Err(e) => {
+                eprintln!("Job '{}' failed to lock its baseline: {:?}", self.name, e);
+                None
+            }
+        };
+        self.reload_if_changed();
+        lock
+    }
+
+    /// Write the in-memory baseline to `baseline_file`. Callers hold `lock_baseline`.
+    fn persist(&mut self) {
+        if let Err(e) = save_baseline(
+            &self.baseline_file,
+            &self.baseline,
+            &self.name,
+            &self.cfg,
+            self.keys.as_ref(),
+        ) {
+            eprintln!("Job '{}' failed to persist baseline: {:?}", self.name, e);
+        }
+        self.baseline_mtime = modified_time(&self.baseline_file);
+    }
+
+    /// Pick up a baseline file rewritten by someone else (`watchdogfs accept`,
+    /// `watchdogfs baseline`). With signing, a file that does not verify raises a
+    /// `tampered` alert and the in-memory baseline is kept.
+    fn reload_if_changed(&mut self) {
+        let mtime = modified_time(&self.baseline_file);
+        if mtime.is_none() || mtime == self.baseline_mtime {
+            return;
+        }
+        self.baseline_mtime = mtime;
+        let file = match read_baseline_unverified(&self.baseline_file) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("Job '{}' failed to reload baseline: {:?}", self.name, e);
+                return;
+            }
+        };
+        if let Some(keys) = &self.keys
+            && let Err(e) = file.verify(keys, &self.name)
+        {
+            let path = self.baseline_file.to_string_lossy().into_owned();
+            dispatch(&self.cfg.alerts, &self.name, &Change::tampered(path));
+            eprintln!(
+                "Job '{}': ignoring rewritten baseline {:?}: {:?}",
+                self.name, self.baseline_file, e
+            );
+            return;
+        }
+        if file.entries != self.baseline {
+            self.baseline = file.entries;
+            println!(
+                "Job '{}': reloaded baseline {:?} after it changed on disk",
+                self.name, self.baseline_file
+            );
+        }
+    }
+
+    /// Re-record the digests of unchanged files whose baseline entry was made with other
+    /// `hash_algorithms` than the job now uses. Their content was just verified with the
+    /// algorithms both sides share, so switching algorithms never raises an alert.
+    fn migrate_digests(&mut self, current: &Baseline, changes: &[Change]) {
+        let wanted = self.entry_opts.hash_algorithms.clone();
+        let stale = |entry: &Entry| {
+            entry.hashes.len() != wanted.len()
+                || wanted.iter().any(|alg| !entry.hashes.contains_key(alg))
+        };
+        if !self.baseline.values().any(stale) {
+            return;
+        }
+        let _lock = self.lock_baseline();
+        let mut migrated = 0;
+        for (path, entry) in self.baseline.iter_mut() {
+            let Some(now) = current.get(path) else {
+                continue;
+            };
+            if !stale(entry)
+                || entry.hashed != now.hashed
+                || changes.iter().any(|c| &c.path == path)
+            {
+                continue;
+            }
+            let hashes: Digests = now
+                .hashes
+                .iter()
+                .filter(|(alg, _)| wanted.contains(alg))
+                .map(|(alg, hex)| (*alg, hex.clone()))
+                .collect();
+            if hashes.len() == wanted.len() {
+                entry.hashes = hashes;
+                migrated += 1;
+            }
+        }
+        if migrated > 0 {
+            let names: Vec<_> = wanted.iter().map(|alg| alg.as_str()).collect();
+            println!(
+                "Job '{}': re-hashed {} file(s) in the baseline with {}",
+                self.name,
+                migrated,
+                names.join(", ")
+            );
+            self.persist();
+        }
+    }
+}
+
 /// Spawn a new thread to run exactly one job (either inotify‐based or polling‐based).
 /// Returns the thread’s `JoinHandle<()>`. The `stop_rx` will be closed or receive a value
 /// to signal this thread to stop.
 fn spawn_job_thread(
     job_name: String,
     job_cfg: JobConfig,
-    mut baseline_map: Baseline,
+    baseline_map: Baseline,
+    keys: Option<Keys>,
+    store: Option<ContentStore>,
     stop_rx: Receiver<()>,
 ) -> thread::JoinHandle<()> {
     thread::spawn(move || {
-        if job_cfg.watcher.mode.as_str() == "inotify" {
-            if let Err(e) = run_inotify_job(
-                job_name.clone(),
-                job_cfg.clone(),
-                &mut baseline_map,
-                stop_rx,
-            ) {
-                eprintln!("Job '{}' inotify error: {:?}", job_name, e);
+        let walk_opts = match WalkOptions::for_job(&job_cfg) {
+            Ok(opts) => opts,
+            Err(e) => {
+                eprintln!("Job '{}': {:?}", job_name, e);
+                return;
             }
-        } else if job_cfg.watcher.mode.as_str() == "poll" {
-            if let Err(e) = run_polling_job(
-                job_name.clone(),
-                job_cfg.clone(),
-                &mut baseline_map,
-                stop_rx,
-            ) {
-                eprintln!("Job '{}' polling error: {:?}", job_name, e);
+        };
+        let text_diff = match TextDiff::for_job(&job_cfg) {
+            Ok(text_diff) => text_diff,
+            Err(e) => {
+                eprintln!("Job '{}': {:?}", job_name, e);
+                return;
+            }
+        };
+        let baseline_file = baseline_path(&job_name);
+        let mut job = JobState {
+            restore_guard: RestoreGuard::new(job_cfg.restore_limit),
+            baseline_mtime: modified_time(&baseline_file),
+            baseline_file,
+            entry_opts: EntryOptions::for_job(&job_cfg),
+            name: job_name,
+            cfg: job_cfg,
+            walk_opts,
+            baseline: baseline_map,
+            keys,
+            store,
+            text_diff,
+            hash_cache: None,
+        };
+
+        if matches!(job.cfg.watcher.mode.as_str(), "inotify" | "hybrid") {
+            if let Err(e) = run_inotify_job(&mut job, stop_rx) {
+                eprintln!("Job '{}' inotify error: {:?}", job.name, e);
+            }
+        } else if job.cfg.watcher.mode.as_str() == "poll" {
+            if let Err(e) = run_polling_job(&mut job, stop_rx) {
+                eprintln!("Job '{}' polling error: {:?}", job.name, e);
+            }
+        } else if job.cfg.watcher.mode.as_str() == "scheduled" {
+            if let Err(e) = run_scheduled_job(&mut job, stop_rx) {
+                eprintln!("Job '{}' scheduled scan error: {:?}", job.name, e);
             }
         } else {
             eprintln!(
                 "Job '{}': unknown watcher mode '{}'",
-                job_name, job_cfg.watcher.mode
+                job.name, job.cfg.watcher.mode
             );
         }
     })
 }
 
-/// Run a single job in “inotify” mode. The thread registers all paths via notify,
-/// then blocks on `stop_rx.recv()`. When `stop_rx` yields a value (or is closed), we drop
-/// the watcher and return, allowing the thread to exit.
-fn run_inotify_job(
-    _job_name: String,
-    job_cfg: JobConfig,
-    baseline: &mut Baseline,
-    stop_rx: Receiver<()>,
-) -> Result<()> {
-    let debounce = Duration::from_millis(job_cfg.watcher.debounce_ms.unwrap_or(500));
-    let watch_paths = job_cfg.watch_paths.clone();
+/// How long the source half of a rename waits for its destination before the file is
+/// treated as moved out of the watched tree. inotify queues both halves back to back.
+const RENAME_PAIR_TIMEOUT: Duration = Duration::from_millis(100);
 
-    // `last_seen` tracks debouncing per-file
-    let mut last_seen: HashMap<String, Instant> = HashMap::new();
-    let cfg_for_cb = job_cfg.clone();
-    let mut baseline_for_cb = baseline.clone();
+/// Run a single job in “inotify” or “hybrid” mode. The thread registers all paths via
+/// notify, whose callback forwards events into a channel, then reconciles the baseline with
+/// the disk (events arriving meanwhile are queued) and handles events until `stop_rx` yields
+/// a value (or is closed). We then drop the watcher and return, allowing the thread to exit.
+///
+/// In hybrid mode the whole tree is also verified every `rescan_interval`. Should inotify
+/// run out of watches, the job carries on in polling mode (see `degrade_to_polling`).
+fn run_inotify_job(job: &mut JobState, stop_rx: Receiver<()>) -> Result<()> {
+    let delay = Duration::from_millis(job.cfg.watcher.debounce_ms.unwrap_or(500));
+    let mut debouncer = Debouncer::new(job.cfg.watcher.debounce, delay);
+    let mut renames = RenameTracker::new(RENAME_PAIR_TIMEOUT);
+    let hybrid = job.cfg.watcher.mode == "hybrid";
+    let rescan_interval = Duration::from_secs(job.cfg.watcher.rescan_interval.unwrap_or(3600));
 
+    let (event_tx, event_rx) = mpsc::channel();
     let mut watcher: RecommendedWatcher = RecommendedWatcher::new(
         move |res: NotifyResult<Event>| {
-            on_event_inotify(
-                res,
-                &cfg_for_cb,
-                &mut baseline_for_cb,
-                &mut last_seen,
-                debounce,
-            )
+            let _ = event_tx.send(res);
         },
         NotifyConfig::default(),
     )?;
 
-    for path in &watch_paths {
-        watcher.watch(path.as_ref(), RecursiveMode::Recursive)?;
+    let mut root_inodes = HashMap::new();
+    for path in &job.cfg.watch_paths {
+        match watcher.watch(path.as_ref(), RecursiveMode::Recursive) {
+            Ok(()) => {}
+            Err(e) if matches!(e.kind, NotifyErrorKind::MaxFilesWatch) => {
+                // Give back the watches added so far
+                drop(watcher);
+                return degrade_to_polling(job, stop_rx, &e);
+            }
+            Err(e) => return Err(e.into()),
+        }
+        root_inodes.insert(path.clone(), inode(path));
     }
 
-    // BLOCK here until we receive a stop signal
-    let _ = stop_rx.recv();
+    // Report anything that changed while we were not watching
+    reconcile(job);
+    let mut next_rescan = Instant::now() + rescan_interval;
+
+    // Handle events until we are told to stop (or our sender goes away)
+    while let Err(TryRecvError::Empty) = stop_rx.try_recv() {
+        job.reload_if_changed();
+        // Wake up in time for the next path that goes quiet
+        let mut timeout = Duration::from_millis(200);
+        if let Some(due) = debouncer
+            .next_due()
+            .into_iter()
+            .chain(renames.next_expiry())
+            .chain(hybrid.then_some(next_rescan))
+            .min()
+        {
+            timeout = timeout.min(due.saturating_duration_since(Instant::now()));
+        }
+        let received = event_rx.recv_timeout(timeout);
+        // Before the event, so a watched file replaced by rename is reported as such
+        rewatch_replaced_roots(&mut watcher, &mut root_inodes, job);
+        match received {
+            // notify stopped adding watches for new directories
+            Ok(Err(e)) if matches!(e.kind, NotifyErrorKind::MaxFilesWatch) => {
+                drop(watcher);
+                return degrade_to_polling(job, stop_rx, &e);
+            }
+            Ok(res) => on_event_inotify(res, job, &mut debouncer, &mut renames, Instant::now()),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        // Renamed out of the watched tree
+        let moved_out = renames.expired(Instant::now());
+        check_paths(
+            job,
+            moved_out
+                .iter()
+                .map(|p| p.to_string_lossy().into_owned())
+                .collect(),
+        );
+        check_paths(job, debouncer.due(Instant::now()));
+        if hybrid && Instant::now() >= next_rescan {
+            rescan(job, "scheduled verification");
+            next_rescan = Instant::now() + rescan_interval;
+        }
+    }
     Ok(())
 }
 
-/// Event handler for inotify-based jobs. Calculates a new checksum, compares with the baseline,
-/// and fires `dispatch(...)` if it changed. Uses `last_seen` + `debounce` to avoid duplicates.
+/// Compare every watch path with the whole baseline: the scheduled verification pass of
+/// hybrid mode, and the recovery after the inotify queue overflowed and events were lost.
+fn rescan(job: &mut JobState, reason: &str) {
+    let Some(current) = snapshot_or_log(job, &job.cfg.watch_paths.clone()) else {
+        return;
+    };
+    let changes = compare(&job.baseline, &current, &job.cfg.attributes);
+    if !changes.is_empty() {
+        println!(
+            "Job '{}': {} found {} change(s)",
+            job.name,
+            reason,
+            changes.len()
+        );
+    }
+    job.handle_changes(changes);
+}
+
+/// inotify ran out of watches (`fs.inotify.max_user_watches`), so events under part of the
+/// tree would be lost without notice. Send a `degraded` warning alert and keep watching the
+/// job by polling instead.
+fn degrade_to_polling(
+    job: &mut JobState,
+    stop_rx: Receiver<()>,
+    error: &notify::Error,
+) -> Result<()> {
+    let path = error
+        .paths
+        .first()
+        .map(|p| p.to_string_lossy().into_owned())
+        .or_else(|| job.cfg.watch_paths.first().cloned())
+        .unwrap_or_default();
+    let message = format!(
+        "inotify watch limit reached at {} (fs.inotify.max_user_watches), polling every {}s instead",
+        path,
+        job.cfg.watcher.poll_interval.unwrap_or(5)
+    );
+    eprintln!("Job '{}': {}", job.name, message);
+    let warning = Finding::new(Severity::Warning, message);
+    dispatch(&job.cfg.alerts, &job.name, &Change::degraded(path, warning));
+    run_polling_job(job, stop_rx)
+}
+
+/// Inode of `path`, if it exists.
+fn inode(path: &str) -> Option<u64> {
+    fs::metadata(path).ok().map(|m| m.ino())
+}
+
+/// inotify watches inodes, so a watch path that is a file loses its watch once another
+/// file is renamed over it (an atomic save). Re-add the watch of every watch path whose
+/// inode changed, and check it: a file that differs from the baseline is reported as
+/// `replaced`.
+fn rewatch_replaced_roots(
+    watcher: &mut impl Watcher,
+    inodes: &mut HashMap<String, Option<u64>>,
+    job: &mut JobState,
+) {
+    for (root, recorded) in inodes.iter_mut() {
+        let current = inode(root);
+        if current == *recorded {
+            continue;
+        }
+        *recorded = current;
+        // Gone: the removal event reports it
+        if current.is_none() {
+            continue;
+        }
+        let _ = watcher.unwatch(Path::new(root));
+        if let Err(e) = watcher.watch(Path::new(root), RecursiveMode::Recursive) {
+            eprintln!("Job '{}' failed to re-watch {}: {:?}", job.name, root, e);
+            continue;
+        }
+        check_replaced(job, None, root.clone());
+    }
+}
+
+/// Compare the whole baseline with the current disk state and alert on every difference.
+/// Runs whenever a job (re)starts, so changes made while watchdogfs was down (or while the
+/// job was being reloaded) are reported before live watching begins.
+fn reconcile(job: &mut JobState) {
+    let Some(current) = snapshot_or_log(job, &job.cfg.watch_paths.clone()) else {
+        return;
+    };
+    let changes = compare(&job.baseline, &current, &job.cfg.attributes);
+    if !changes.is_empty() {
+        println!(
+            "Job '{}': {} change(s) since the baseline was recorded",
+            job.name,
+            changes.len()
+        );
+    }
+    job.migrate_digests(&current, &changes);
+    // Keep the recorded contents that are still on disk, e.g. after enabling `store_contents`
+    if let Some(store) = &job.store {
+        store.put_all(&job.baseline);
+    }
+    job.handle_changes(changes);
+}
+
+/// Event handler for inotify-based jobs. Every path named by the event (a file, or a
+/// directory that appeared or vanished) is re-scanned and compared with the matching part
+/// of the baseline, so creations, deletions, renames and modifications all reach
+/// `dispatch(...)`. Paths that still exist go through `debouncer`, which may defer them
+/// until they are quiet; deletions are never debounced. Ignored paths are dropped up front.
+///
+/// The two halves of a rename are paired by their cookie in `renames` and handled together,
+/// right away: see `check_replaced`. A source whose destination never arrives was moved out
+/// of the watched tree and is checked once `renames` expires it. After a queue overflow the
+/// whole tree is rescanned.
 fn on_event_inotify(
     res: NotifyResult<Event>,
-    cfg: &JobConfig,
-    baseline: &mut Baseline,
-    last_seen: &mut HashMap<String, Instant>,
-    debounce: Duration,
+    job: &mut JobState,
+    debouncer: &mut Debouncer,
+    renames: &mut RenameTracker,
+    now: Instant,
 ) {
-    if let Ok(event) = res {
-        for path_buf in event.paths {
-            let path_str = path_buf.to_string_lossy().into_owned();
-            let now = Instant::now();
-
-            // Debounce: skip if we've seen it recently
-            let prev = last_seen
-                .get(&path_str)
-                .cloned()
-                .unwrap_or_else(|| now - debounce * 2);
-            if now.duration_since(prev) < debounce {
-                continue;
+    let Ok(event) = res else {
+        return;
+    };
+    // The kernel queue overflowed: any event may have been dropped
+    if event.need_rescan() {
+        rescan(job, "rescan after inotify queue overflow");
+        return;
+    }
+    if let EventKind::Modify(ModifyKind::Name(mode)) = event.kind
+        && let (Some(cookie), Some(path)) = (event.attrs.tracker(), event.paths.first())
+    {
+        match mode {
+            RenameMode::From => {
+                renames.moved_from(cookie, path.clone(), now);
+                return;
             }
-            last_seen.insert(path_str.clone(), now);
+            RenameMode::To => {
+                if let Some(from) = renames.moved_to(cookie) {
+                    let from = from.to_string_lossy().into_owned();
+                    debouncer.forget(&from);
+                    check_replaced(job, Some(from), path.to_string_lossy().into_owned());
+                    return;
+                }
+            }
+            // Already handled as its two halves
+            RenameMode::Both => return,
+            _ => {}
+        }
+    }
+    // A directory only needs re-walking when it appears (created or moved in)
+    let rescan_dirs = matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))
+    );
 
-            // Compute checksum and compare to baseline
-            if let Ok(new_sum) = calculate_checksum(&path_str) {
-                if let Some(old_sum) = baseline.insert(path_str.clone(), new_sum.clone()) {
-                    if old_sum != new_sum {
-                        dispatch(&cfg.alerts, &path_buf, old_sum, new_sum.clone());
-                    }
+    let mut roots = Vec::new();
+    for path_buf in &event.paths {
+        let is_dir = path_buf.is_dir();
+        if job.walk_opts.ignore.is_ignored(path_buf, is_dir) || (is_dir && !rescan_dirs) {
+            continue;
+        }
+        let path_str = path_buf.to_string_lossy().into_owned();
+        if path_buf.exists() && !debouncer.event(&path_str, now) {
+            continue;
+        }
+        roots.push(path_str);
+    }
+    check_paths(job, roots);
+}
+
+/// Check `target` together with the file renamed over it, if any (`from`). An existing
+/// baseline file that now differs was replaced, as by the write-to-temp-then-rename() of
+/// editors, `sed -i` and package managers, and is reported as one `replaced` change rather
+/// than a creation and a modification; other renames are reported as such.
+fn check_replaced(job: &mut JobState, from: Option<String>, target: String) {
+    if job
+        .walk_opts
+        .ignore
+        .is_ignored(Path::new(&target), Path::new(&target).is_dir())
+    {
+        check_paths(job, from.into_iter().collect());
+        return;
+    }
+    let mut roots: Vec<_> = from.iter().cloned().collect();
+    roots.push(target.clone());
+    let Some(current) = snapshot_or_log(job, &roots) else {
+        return;
+    };
+    let stored = baseline_under(&job.baseline, &roots);
+    let changes = compare(&stored, &current, &job.cfg.attributes)
+        .into_iter()
+        .map(|change| {
+            if change.path == target {
+                change.into_replaced(from.clone())
+            } else {
+                change
+            }
+        })
+        .collect();
+    job.handle_changes(changes);
+}
+
+/// Compare what is on disk now under `roots` with what the baseline says about the same
+/// paths, and handle the differences.
+fn check_paths(job: &mut JobState, roots: Vec<String>) {
+    if roots.is_empty() {
+        return;
+    }
+    let Some(current) = snapshot_or_log(job, &roots) else {
+        return;
+    };
+    let stored = baseline_under(&job.baseline, &roots);
+    job.handle_changes(compare(&stored, &current, &job.cfg.attributes));
+}
+
+/// Hash and stat the files currently on disk under `roots` that belong to `job`. A file
+/// that exists but cannot be read keeps its baseline entry, so a transient read error is
+/// not mistaken for a deletion. Fails if the files cannot be listed.
+fn snapshot(job: &mut JobState, roots: &[String]) -> Result<Baseline> {
+    let files = collect_files(roots, &job.walk_opts)
+        .with_context(|| format!("listing the files of job '{}'", job.name))?;
+
+    let mut current = Baseline::new();
+    for path_str in files {
+        let path = Path::new(&path_str);
+        if !path.exists() || !is_watched(&job.cfg.watch_paths, path, &job.walk_opts) {
+            continue;
+        }
+        // Also compute whatever digests the baseline entry has, so it stays comparable
+        let opts = match job.baseline.get(&path_str) {
+            Some(old_entry) => job.entry_opts.covering(old_entry),
+            None => job.entry_opts.clone(),
+        };
+        let entry = match &mut job.hash_cache {
+            Some(cache) => cache.entry(&path_str, &opts),
+            None => Entry::from_path(&path_str, &opts),
+        };
+        match entry {
+            Ok(entry) => {
+                current.insert(path_str, entry);
+            }
+            Err(e) => {
+                eprintln!(
+                    "Job '{}' failed checksum on {}: {:?}",
+                    job.name, path_str, e
+                );
+                if let Some(old_entry) = job.baseline.get(&path_str) {
+                    current.insert(path_str.clone(), old_entry.clone());
                 }
             }
         }
     }
+    Ok(current)
 }
 
-/// Run a single job in “polling” mode. We keep all watched paths in one loop,
-/// sleeping for `poll_interval` between iterations. We also implement debouncing
-/// per-path (using a `last_seen` map). When `stop_rx` yields (or the channel is closed),
-/// we break and the thread exits.
-fn run_polling_job(
-    job_name: String,
-    job_cfg: JobConfig,
-    baseline: &mut Baseline,
-    stop_rx: Receiver<()>,
-) -> Result<()> {
-    let interval = Duration::from_secs(job_cfg.watcher.poll_interval.unwrap_or(5));
-    let debounce = Duration::from_millis(job_cfg.watcher.debounce_ms.unwrap_or(500));
+/// `snapshot`, or `None` after logging the error: the caller then skips the check, and
+/// the next event or pass tries again.
+fn snapshot_or_log(job: &mut JobState, roots: &[String]) -> Option<Baseline> {
+    snapshot(job, roots)
+        .inspect_err(|e| eprintln!("Job '{}' failed to list files: {:?}", job.name, e))
+        .ok()
+}
+
+/// The part of `baseline` describing `roots`: each root itself and everything below it.
+fn baseline_under(baseline: &Baseline, roots: &[String]) -> Baseline {
+    baseline
+        .iter()
+        .filter(|(path, _)| roots.iter().any(|root| Path::new(path).starts_with(root)))
+        .map(|(path, sum)| (path.clone(), sum.clone()))
+        .collect()
+}
+
+/// Run a single job in “polling” mode. After an initial reconciliation, every
+/// `poll_interval` the watched paths are re-walked and the result is compared with the
+/// baseline, so new, deleted, renamed and modified files are all reported. Only files
+/// whose stat changed since they were last hashed are read again (see `HashCache`),
+/// except on the passes every `full_rehash_interval`, which hash everything. We also
+/// implement debouncing per-path (using a `last_seen` map). When `stop_rx` yields (or
+/// the channel is closed), we break and the thread exits.
+fn run_polling_job(job: &mut JobState, stop_rx: Receiver<()>) -> Result<()> {
+    let interval = Duration::from_secs(job.cfg.watcher.poll_interval.unwrap_or(5));
+    let debounce = Duration::from_millis(job.cfg.watcher.debounce_ms.unwrap_or(500));
+    let mut last_full_rehash = Instant::now();
 
     // Track last time we fired for each path, to handle per-path debounce.
     let mut last_seen: HashMap<String, Instant> = HashMap::new();
-    // For convenience, clone the list of paths here
-    let watch_paths = job_cfg.watch_paths.clone();
+
+    job.hash_cache = Some(HashCache::load(
+        hashcache::path_for(&job.name),
+        job.keys.as_ref(),
+    ));
+    // Report anything that changed while we were not watching
+    reconcile(job);
+    job.save_hash_cache(None);
 
     loop {
         // 1) Check for stop signal
@@ -319,40 +1034,600 @@ fn run_polling_job(
         // 2) Sleep for the polling interval
         thread::sleep(interval);
 
-        // 3) For each watched path, see if it changed
-        for path_str in &watch_paths {
-            let now = Instant::now();
-            // Debounce: skip if seen too recently
-            let prev = last_seen
-                .get(path_str)
-                .cloned()
-                .unwrap_or_else(|| now - debounce * 2);
-            if now.duration_since(prev) < debounce {
-                continue;
+        // 3) Re-scan every watched path and compare against the (possibly reloaded) baseline
+        job.reload_if_changed();
+        job.clear_hash_cache_if_due(&mut last_full_rehash);
+        let Some(current) = snapshot_or_log(job, &job.cfg.watch_paths.clone()) else {
+            continue;
+        };
+        job.save_hash_cache(Some(&current));
+        let now = Instant::now();
+        let changes: Vec<Change> = compare(&job.baseline, &current, &job.cfg.attributes)
+            .into_iter()
+            .filter(|change| {
+                // Debounce: skip if we alerted on this path too recently
+                let prev = last_seen
+                    .get(&change.path)
+                    .cloned()
+                    .unwrap_or_else(|| now - debounce * 2);
+                now.duration_since(prev) >= debounce
+            })
+            .collect();
+
+        // Fire the alerts and update baseline + last_seen
+        for change in &changes {
+            last_seen.insert(change.path.clone(), now);
+        }
+        job.handle_changes(changes);
+    }
+
+    Ok(())
+}
+
+/// Run a single job in “scheduled” mode: a full integrity pass against the baseline at
+/// every run time of `schedule` (plus up to `jitter_secs`). Passes hash every file, without
+/// the hash cache: nothing watches these trees in between, so a forged stat or cache must
+/// not hide a change until the next one. There is no pass at startup. Each pass
+/// dispatches the usual alerts and then a `summary` alert. Run times that pass while a
+/// pass is still going are skipped, or with `catch_up` make one more pass right away. The
+/// thread waits for the next run in short steps, so it stops promptly when `stop_rx`
+/// yields (or is closed).
+fn run_scheduled_job(job: &mut JobState, stop_rx: Receiver<()>) -> Result<()> {
+    let schedule = Schedule::for_watcher(&job.cfg.watcher)?;
+    job.hash_cache = None;
+
+    let mut next = schedule.next_run(&Local::now())?;
+    loop {
+        // Sleep until `next`, re-reading the clock so suspend and clock changes are noticed
+        while Local::now() < next {
+            let left = (next - Local::now()).to_std().unwrap_or_default();
+            match stop_rx.recv_timeout(left.min(Duration::from_secs(60))) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => return Ok(()),
             }
+        }
+
+        job.reload_if_changed();
+        let due = next;
+        scheduled_pass(job);
+
+        let catch_up = job.cfg.watcher.catch_up;
+        let (after, missed) = schedule.after_pass(&due, &Local::now(), catch_up)?;
+        if missed > 0 && !catch_up {
+            println!(
+                "Job '{}': skipped {} scheduled run(s) while the previous one was still going",
+                job.name, missed
+            );
+        }
+        next = after;
+    }
+}
+
+/// One scheduled pass over every watch path: handle the changes, then send the summary,
+/// which is the returned change. A pass that cannot list the files sends a critical
+/// summary instead.
+fn scheduled_pass(job: &mut JobState) -> Change {
+    let started = Instant::now();
+    let paths = job.cfg.watch_paths.join(", ");
+    let summary = match snapshot(job, &job.cfg.watch_paths.clone()) {
+        Ok(current) => {
+            let changes = compare(&job.baseline, &current, &job.cfg.attributes);
+            let summary = summarize(current.len(), &changes, started.elapsed());
+            job.handle_changes(changes);
+            summary
+        }
+        Err(e) => Finding::new(
+            Severity::Critical,
+            format!(
+                "scan failed after {:.1}s: {:#}",
+                started.elapsed().as_secs_f64(),
+                e
+            ),
+        ),
+    };
+    println!("Job '{}': {}", job.name, summary.message);
+    let change = Change::summary(paths, summary);
+    dispatch(&job.cfg.alerts, &job.name, &change);
+    change
+}
+
+/// The outcome of a scheduled pass over `files` files, e.g. "scanned 1200 file(s) in 3.2s:
+/// 1 created, 2 modified". A warning if anything changed, info otherwise.
+fn summarize(files: usize, changes: &[Change], took: Duration) -> Finding {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for change in changes {
+        *counts.entry(change.kind.as_str()).or_default() += 1;
+    }
+    let scanned = format!("scanned {} file(s) in {:.1}s", files, took.as_secs_f64());
+    if counts.is_empty() {
+        return Finding::new(Severity::Info, format!("{}: no changes", scanned));
+    }
+    let counts = counts
+        .iter()
+        .map(|(kind, n)| format!("{} {}", n, kind))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Finding::new(Severity::Warning, format!("{}: {}", scanned, counts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Debouncer, JobState, RENAME_PAIR_TIMEOUT, RenameTracker, check_paths, on_event_inotify,
+        reconcile, scheduled_pass, summarize,
+    };
+    use crate::config::{
+        BaselineUpdate, ContentStoreConfig, DebounceMode, HashAlgorithm, JobConfig, Remediation,
+        RestoreLimit,
+    };
+    use crate::integrity::restore::RestoreGuard;
+    use crate::integrity::semantic::Severity;
+    use crate::integrity::store::ContentStore;
+    use crate::integrity::{
+        Baseline, Change, Entry, EntryOptions, WalkOptions, load_baseline, save_baseline,
+    };
+    use notify::{
+        Event, EventKind,
+        event::{CreateKind, Flag, ModifyKind, RemoveKind, RenameMode},
+    };
+    use std::{
+        ffi::CString,
+        fs,
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        os::fd::{AsRawFd, FromRawFd},
+        path::Path,
+        sync::mpsc::{self, Receiver},
+        thread,
+        time::{Duration, Instant},
+    };
+    use tempfile::{TempDir, tempdir};
+
+    /// A job watching `dir`, persisting its baseline into a separate temp dir.
+    fn job_for(dir: &Path, baseline: Baseline) -> (JobState, TempDir) {
+        let state_dir = tempdir().unwrap();
+        let job = JobState {
+            name: "web".into(),
+            cfg: JobConfig {
+                watch_paths: vec![dir.to_str().unwrap().to_string()],
+                ..Default::default()
+            },
+            walk_opts: WalkOptions::default(),
+            entry_opts: EntryOptions::default(),
+            baseline,
+            baseline_file: state_dir.path().join("baseline_web.json"),
+            keys: None,
+            baseline_mtime: None,
+            store: None,
+            restore_guard: RestoreGuard::new(Default::default()),
+            text_diff: None,
+            hash_cache: None,
+        };
+        (job, state_dir)
+    }
 
-            // Attempt to calculate a new checksum
-            match calculate_checksum(path_str) {
-                Ok(new_sum) => {
-                    if let Some(old_sum) = baseline.get(path_str).cloned() {
-                        if old_sum != new_sum {
-                            // Fire the alert and update baseline + last_seen
-                            let path_buf = PathBuf::from(path_str);
-                            dispatch(&job_cfg.alerts, &path_buf, old_sum.clone(), new_sum.clone());
-                            baseline.insert(path_str.clone(), new_sum.clone());
-                            last_seen.insert(path_str.clone(), now);
-                        }
+    fn renames() -> RenameTracker {
+        RenameTracker::new(RENAME_PAIR_TIMEOUT)
+    }
+
+    #[test]
+    fn created_file_is_added_to_baseline() {
+        let dir = tempdir().unwrap();
+        let (mut job, _state) = job_for(dir.path(), Baseline::new());
+        let shell = dir.path().join("shell.php");
+        fs::write(&shell, "<?php system($_GET['c']);").unwrap();
+
+        let event = Event::new(EventKind::Create(CreateKind::File)).add_path(shell.clone());
+        let mut debouncer = Debouncer::new(DebounceMode::Leading, Duration::ZERO);
+        on_event_inotify(
+            Ok(event),
+            &mut job,
+            &mut debouncer,
+            &mut renames(),
+            Instant::now(),
+        );
+        assert!(job.baseline.contains_key(shell.to_str().unwrap()));
+    }
+
+    #[test]
+    fn trailing_debounce_hashes_the_final_write() {
+        let dir = tempdir().unwrap();
+        let conf = dir.path().join("sshd_config");
+        fs::write(&conf, "PermitRootLogin no\n").unwrap();
+        let conf_str = conf.to_str().unwrap().to_string();
+        let mut baseline = Baseline::new();
+        baseline.insert(
+            conf_str.clone(),
+            Entry::from_path(&conf_str, &EntryOptions::default()).unwrap(),
+        );
+        let (mut job, _state) = job_for(dir.path(), baseline);
+        let mut debouncer = Debouncer::new(DebounceMode::Trailing, Duration::from_millis(500));
+        let start = Instant::now();
+        let modified = || Event::new(EventKind::Create(CreateKind::File)).add_path(conf.clone());
+
+        // An editor writing twice in quick succession
+        fs::write(&conf, "PermitRootLogin yes\n").unwrap();
+        on_event_inotify(
+            Ok(modified()),
+            &mut job,
+            &mut debouncer,
+            &mut renames(),
+            start,
+        );
+        fs::write(&conf, "PermitRootLogin yes\nPasswordAuthentication yes\n").unwrap();
+        on_event_inotify(
+            Ok(modified()),
+            &mut job,
+            &mut debouncer,
+            &mut renames(),
+            start + Duration::from_millis(50),
+        );
+        check_paths(&mut job, debouncer.due(start + Duration::from_millis(400)));
+        assert!(!job.baseline_file.exists());
+
+        check_paths(&mut job, debouncer.due(start + Duration::from_millis(550)));
+        assert_eq!(
+            job.baseline[&conf_str],
+            Entry::from_path(&conf_str, &EntryOptions::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn atomic_save_replaces_the_target() {
+        let dir = tempdir().unwrap();
+        let conf = dir.path().join("sudoers");
+        fs::write(&conf, "root ALL=(ALL:ALL) ALL\n").unwrap();
+        let conf_str = conf.to_str().unwrap().to_string();
+        let mut baseline = Baseline::new();
+        baseline.insert(
+            conf_str.clone(),
+            Entry::from_path(&conf_str, &EntryOptions::default()).unwrap(),
+        );
+        let (mut job, _state) = job_for(dir.path(), baseline);
+        let mut debouncer = Debouncer::new(DebounceMode::Trailing, Duration::from_millis(500));
+        let mut renames = renames();
+        let start = Instant::now();
+
+        // Write a temp file, then rename() it over the target
+        let temp = dir.path().join("sudoers.tmp");
+        fs::write(
+            &temp,
+            "root ALL=(ALL:ALL) ALL\nmallory ALL=(ALL) NOPASSWD: ALL\n",
+        )
+        .unwrap();
+        let created = Event::new(EventKind::Create(CreateKind::File)).add_path(temp.clone());
+        on_event_inotify(Ok(created), &mut job, &mut debouncer, &mut renames, start);
+        fs::rename(&temp, &conf).unwrap();
+        let rename = |mode| Event::new(EventKind::Modify(ModifyKind::Name(mode))).set_tracker(7);
+        let from = rename(RenameMode::From).add_path(temp.clone());
+        let to = rename(RenameMode::To).add_path(conf.clone());
+        let both = rename(RenameMode::Both).add_path(temp).add_path(conf);
+        for event in [from, to, both] {
+            on_event_inotify(Ok(event), &mut job, &mut debouncer, &mut renames, start);
+        }
+
+        // Handled at once: the target has its new entry, the temp file was never seen
+        let saved = load_baseline(&job.baseline_file, "web", None).unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(
+            saved[&conf_str],
+            Entry::from_path(&conf_str, &EntryOptions::default()).unwrap()
+        );
+        assert!(debouncer.due(start + Duration::from_secs(1)).is_empty());
+        assert!(renames.expired(start + Duration::from_secs(1)).is_empty());
+    }
+
+    #[test]
+    fn queue_overflow_rescans_the_tree() {
+        let dir = tempdir().unwrap();
+        let (mut job, _state) = job_for(dir.path(), Baseline::new());
+        // Written while the kernel was dropping events
+        let dropped = dir.path().join("backdoor.sh");
+        fs::write(&dropped, "#!/bin/sh\n").unwrap();
+
+        let overflow = Event::new(EventKind::Other).set_flag(Flag::Rescan);
+        let mut debouncer = Debouncer::new(DebounceMode::Trailing, Duration::from_millis(500));
+        on_event_inotify(
+            Ok(overflow),
+            &mut job,
+            &mut debouncer,
+            &mut renames(),
+            Instant::now(),
+        );
+        assert!(job.baseline.contains_key(dropped.to_str().unwrap()));
+    }
+
+    #[test]
+    fn scheduled_pass_handles_changes() {
+        let dir = tempdir().unwrap();
+        let kept = dir.path().join("ls");
+        fs::write(&kept, "ELF").unwrap();
+        let kept_str = kept.to_str().unwrap().to_string();
+        let mut baseline = Baseline::new();
+        baseline.insert(
+            kept_str.clone(),
+            Entry::from_path(&kept_str, &EntryOptions::default()).unwrap(),
+        );
+        let (mut job, _state) = job_for(dir.path(), baseline);
+        fs::write(&kept, "ELF, patched").unwrap();
+        fs::write(dir.path().join("sshd"), "ELF").unwrap();
+
+        scheduled_pass(&mut job);
+        let saved = load_baseline(&job.baseline_file, "web", None).unwrap();
+        assert_eq!(saved.len(), 2);
+        assert_eq!(
+            saved[&kept_str],
+            Entry::from_path(&kept_str, &EntryOptions::default()).unwrap()
+        );
+    }
+
+    /// A local webhook receiver: the URL to post to, and the JSON bodies it received.
+    fn webhook() -> (String, Receiver<serde_json::Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = BufReader::new(stream.unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
                     }
                 }
-                Err(e) => {
-                    eprintln!(
-                        "Polling job '{}' failed checksum on {}: {:?}",
-                        job_name, path_str, e
-                    );
-                }
+                let mut body = vec![0; length];
+                stream.read_exact(&mut body).unwrap();
+                // Queue the alert before answering, so it is there once dispatch returns
+                tx.send(serde_json::from_slice(&body).unwrap()).unwrap();
+                stream
+                    .get_mut()
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                    .unwrap();
+            }
+        });
+        (url, rx)
+    }
+
+    #[test]
+    fn scheduled_pass_sends_a_summary_alert() {
+        let dir = tempdir().unwrap();
+        let kept = dir.path().join("ls");
+        fs::write(&kept, "ELF").unwrap();
+        let kept_str = kept.to_str().unwrap().to_string();
+        let mut baseline = Baseline::new();
+        baseline.insert(
+            kept_str.clone(),
+            Entry::from_path(&kept_str, &EntryOptions::default()).unwrap(),
+        );
+        let (mut job, _state) = job_for(dir.path(), baseline);
+        let (url, alerts) = webhook();
+        job.cfg.alerts.webhook_url = Some(url);
+        fs::write(&kept, "ELF, patched").unwrap();
+        fs::write(dir.path().join("sshd"), "ELF").unwrap();
+
+        let summary = scheduled_pass(&mut job);
+        let sent: Vec<_> = alerts.try_iter().collect();
+        let kinds: Vec<_> = sent.iter().map(|a| a["kind"].as_str().unwrap()).collect();
+        assert_eq!(kinds, ["modified", "created", "summary"]);
+
+        let alert = &sent[2];
+        assert_eq!(alert["job"], "web");
+        assert_eq!(alert["path"], dir.path().to_str().unwrap());
+        assert_eq!(alert["findings"][0]["severity"], "warning");
+        let message = alert["findings"][0]["message"].as_str().unwrap();
+        assert!(message.starts_with("scanned 2 file(s) in "), "{}", message);
+        assert!(message.ends_with("s: 1 created, 1 modified"), "{}", message);
+        assert_eq!(summary.findings[0].message, message);
+    }
+
+    #[test]
+    fn scheduled_pass_that_cannot_list_files_is_critical() {
+        let dir = tempdir().unwrap();
+        // Nest directories until their path exceeds PATH_MAX, so walking them fails
+        let name = CString::new("d".repeat(200)).unwrap();
+        let mut fd = fs::File::open(dir.path()).unwrap();
+        for _ in 0..25 {
+            // SAFETY: `name` is NUL-terminated and `fd` is an open directory
+            unsafe {
+                assert_eq!(libc::mkdirat(fd.as_raw_fd(), name.as_ptr(), 0o700), 0);
+                let sub = libc::openat(fd.as_raw_fd(), name.as_ptr(), libc::O_DIRECTORY);
+                assert!(sub >= 0);
+                fd = fs::File::from_raw_fd(sub);
             }
         }
+        let (mut job, _state) = job_for(dir.path(), Baseline::new());
+        let (url, alerts) = webhook();
+        job.cfg.alerts.webhook_url = Some(url);
+
+        let summary = scheduled_pass(&mut job);
+        assert_eq!(summary.findings[0].severity, Severity::Critical);
+        assert!(
+            summary.findings[0]
+                .message
+                .starts_with("scan failed after ")
+        );
+        let sent: Vec<_> = alerts.try_iter().collect();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0]["findings"][0]["severity"], "critical");
+        assert!(!job.baseline_file.exists());
     }
 
-    Ok(())
+    #[test]
+    fn summary_counts_changes_by_kind() {
+        let old = Entry::from("1");
+        let changes = [
+            Change::deleted("/usr/bin/a".into(), old.clone()),
+            Change::created("/usr/bin/b".into(), old.clone()),
+            Change::deleted("/usr/bin/c".into(), old),
+        ];
+        let took = Duration::from_millis(2345);
+        let summary = summarize(1200, &changes, took);
+        assert_eq!(summary.severity, Severity::Warning);
+        assert_eq!(
+            summary.message,
+            "scanned 1200 file(s) in 2.3s: 1 created, 2 deleted"
+        );
+        let clean = summarize(1200, &[], took);
+        assert_eq!(clean.severity, Severity::Info);
+        assert_eq!(clean.message, "scanned 1200 file(s) in 2.3s: no changes");
+    }
+
+    #[test]
+    fn removed_directory_drops_its_files() {
+        let dir = tempdir().unwrap();
+        let sub = dir.path().join("conf.d");
+        fs::create_dir(&sub).unwrap();
+        let file = sub.join("site.conf");
+        fs::write(&file, "listen 80;").unwrap();
+        let path = file.to_str().unwrap().to_string();
+        let mut baseline = Baseline::new();
+        baseline.insert(
+            path.clone(),
+            Entry::from_path(&path, &EntryOptions::default()).unwrap(),
+        );
+        let (mut job, _state) = job_for(dir.path(), baseline);
+
+        fs::remove_dir_all(&sub).unwrap();
+        let event = Event::new(EventKind::Remove(RemoveKind::Folder)).add_path(sub);
+        let mut debouncer = Debouncer::new(DebounceMode::Trailing, Duration::from_millis(500));
+        on_event_inotify(
+            Ok(event),
+            &mut job,
+            &mut debouncer,
+            &mut renames(),
+            Instant::now(),
+        );
+        assert!(job.baseline.is_empty());
+    }
+
+    #[test]
+    fn switching_hash_algorithms_migrates_without_alerts() {
+        let dir = tempdir().unwrap();
+        let conf = dir.path().join("app.conf");
+        fs::write(&conf, "debug = false").unwrap();
+        let conf_str = conf.to_str().unwrap().to_string();
+        let mut baseline = Baseline::new();
+        baseline.insert(
+            conf_str.clone(),
+            Entry::from_path(&conf_str, &EntryOptions::default()).unwrap(),
+        );
+        let (mut job, _state) = job_for(dir.path(), baseline);
+        job.cfg.hash_algorithms = vec![HashAlgorithm::Blake3];
+        job.entry_opts = EntryOptions::for_job(&job.cfg);
+
+        reconcile(&mut job);
+        let hashes = &job.baseline[&conf_str].hashes;
+        assert_eq!(hashes.keys().collect::<Vec<_>>(), [&HashAlgorithm::Blake3]);
+        assert_eq!(
+            load_baseline(&job.baseline_file, "web", None).unwrap(),
+            job.baseline
+        );
+    }
+
+    #[test]
+    fn reconcile_catches_offline_changes_and_persists_them() {
+        let dir = tempdir().unwrap();
+        let conf = dir.path().join("app.conf");
+        fs::write(&conf, "debug = false").unwrap();
+        let conf_str = conf.to_str().unwrap().to_string();
+        let gone = dir
+            .path()
+            .join("removed.conf")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let mut baseline = Baseline::new();
+        baseline.insert(conf_str.clone(), "stale-checksum".into());
+        baseline.insert(gone.clone(), "whatever".into());
+        let (mut job, _state) = job_for(dir.path(), baseline);
+
+        reconcile(&mut job);
+        assert_eq!(job.baseline.len(), 1);
+        assert_eq!(
+            job.baseline[&conf_str],
+            Entry::from_path(&conf_str, &EntryOptions::default()).unwrap()
+        );
+        assert_eq!(
+            load_baseline(&job.baseline_file, "web", None).unwrap(),
+            job.baseline
+        );
+    }
+
+    #[test]
+    fn alert_until_accepted_leaves_baseline_alone() {
+        let dir = tempdir().unwrap();
+        let conf = dir.path().join("app.conf");
+        fs::write(&conf, "debug = true").unwrap();
+        let mut baseline = Baseline::new();
+        baseline.insert(conf.to_str().unwrap().to_string(), "stale".into());
+        let (mut job, _state) = job_for(dir.path(), baseline.clone());
+        job.cfg.baseline_update = BaselineUpdate::AlertUntilAccepted;
+
+        reconcile(&mut job);
+        assert_eq!(job.baseline, baseline);
+        assert!(!job.baseline_file.exists());
+    }
+
+    #[test]
+    fn accepted_baseline_is_reloaded() {
+        let dir = tempdir().unwrap();
+        let conf = dir.path().join("nginx.conf");
+        fs::write(&conf, "worker_processes 4;").unwrap();
+        let conf_str = conf.to_str().unwrap().to_string();
+        let mut baseline = Baseline::new();
+        baseline.insert(conf_str.clone(), "old".into());
+        let (mut job, _state) = job_for(dir.path(), baseline);
+        job.cfg.baseline_update = BaselineUpdate::AlertUntilAccepted;
+
+        let mut accepted = Baseline::new();
+        accepted.insert(
+            conf_str.clone(),
+            Entry::from_path(&conf_str, &EntryOptions::default()).unwrap(),
+        );
+        save_baseline(&job.baseline_file, &accepted, "web", &job.cfg, None).unwrap();
+        job.reload_if_changed();
+        assert_eq!(job.baseline, accepted);
+    }
+
+    #[test]
+    fn tampered_file_is_restored_until_the_limit() {
+        let dir = tempdir().unwrap();
+        let keys = dir.path().join("authorized_keys");
+        fs::write(&keys, "ssh-ed25519 AAAA admin\n").unwrap();
+        let keys_str = keys.to_str().unwrap().to_string();
+        let recorded = Entry::from_path(&keys_str, &EntryOptions::default()).unwrap();
+        let mut baseline = Baseline::new();
+        baseline.insert(keys_str.clone(), recorded.clone());
+        let (mut job, state) = job_for(dir.path(), baseline);
+        let store = ContentStore::new(ContentStoreConfig {
+            path: state.path().join("store").to_str().unwrap().to_string(),
+            ..Default::default()
+        });
+        store.put(&keys_str, &recorded).unwrap();
+        job.store = Some(store);
+        job.cfg.remediation = Remediation::Restore;
+        job.restore_guard = RestoreGuard::new(RestoreLimit {
+            max_restores: 1,
+            window_secs: 600,
+        });
+
+        fs::write(&keys, "ssh-ed25519 BBBB intruder\n").unwrap();
+        reconcile(&mut job);
+        assert_eq!(
+            fs::read_to_string(&keys).unwrap(),
+            "ssh-ed25519 AAAA admin\n"
+        );
+        assert!(job.baseline[&keys_str].same_content(&recorded));
+
+        // A second tampering within the window is only alerted on
+        fs::remove_file(&keys).unwrap();
+        reconcile(&mut job);
+        assert!(!keys.exists());
+        assert!(job.baseline.is_empty());
+    }
 }
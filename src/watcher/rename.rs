@@ -0,0 +1,91 @@
+// Pairing of the two halves of an inotify rename by their cookie
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Holds the source half (`MOVED_FROM`) of each rename until the destination half
+/// (`MOVED_TO`) with the same cookie arrives. Time is passed in, so the behaviour
+/// does not depend on the clock.
+#[derive(Debug)]
+pub struct RenameTracker {
+    timeout: Duration,
+    /// Source path and arrival time, by cookie
+    pending: HashMap<usize, (PathBuf, Instant)>,
+}
+
+impl RenameTracker {
+    pub fn new(timeout: Duration) -> Self {
+        RenameTracker {
+            timeout,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Remember that `path` was renamed away at `now`.
+    pub fn moved_from(&mut self, cookie: usize, path: PathBuf, now: Instant) {
+        self.pending.insert(cookie, (path, now));
+    }
+
+    /// The source of the rename whose destination half carries `cookie`, if seen.
+    pub fn moved_to(&mut self, cookie: usize) -> Option<PathBuf> {
+        self.pending.remove(&cookie).map(|(path, _)| path)
+    }
+
+    /// Sources whose destination did not arrive within the timeout, i.e. that were
+    /// moved out of the watched tree, sorted.
+    pub fn expired(&mut self, now: Instant) -> Vec<PathBuf> {
+        let mut expired = Vec::new();
+        self.pending.retain(|_, (path, seen)| {
+            let keep = now.duration_since(*seen) < self.timeout;
+            if !keep {
+                expired.push(path.clone());
+            }
+            keep
+        });
+        expired.sort();
+        expired
+    }
+
+    /// When the oldest unpaired source expires, if any.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.pending
+            .values()
+            .map(|(_, seen)| *seen + self.timeout)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RenameTracker;
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn halves_are_paired_by_cookie() {
+        let start = Instant::now();
+        let mut renames = RenameTracker::new(Duration::from_millis(100));
+        renames.moved_from(7, PathBuf::from("/etc/.sudoers.tmp"), start);
+        renames.moved_from(8, PathBuf::from("/etc/motd"), start);
+        assert_eq!(renames.moved_to(9), None);
+        assert_eq!(
+            renames.moved_to(7),
+            Some(PathBuf::from("/etc/.sudoers.tmp"))
+        );
+        assert_eq!(
+            renames.next_expiry(),
+            Some(start + Duration::from_millis(100))
+        );
+
+        assert!(
+            renames
+                .expired(start + Duration::from_millis(50))
+                .is_empty()
+        );
+        assert_eq!(
+            renames.expired(start + Duration::from_millis(100)),
+            [PathBuf::from("/etc/motd")]
+        );
+        assert_eq!(renames.next_expiry(), None);
+    }
+}